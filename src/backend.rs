@@ -0,0 +1,451 @@
+use crate::compiled::GateOps;
+use crate::types::{Error, Seed, UserId, Word};
+use itertools::Itertools;
+use phantom_zone::{
+    aggregate_server_key_shares,
+    evaluator::NonInteractiveMultiPartyCrs,
+    keys::{
+        CommonReferenceSeededNonInteractiveMultiPartyServerKeyShare,
+        SeededNonInteractiveMultiPartyServerKey,
+    },
+    parameters::BoolParameters,
+    set_common_reference_seed, set_parameter_set, Encryptor, FheBool, KeySwitchWithId,
+    MultiPartyDecryptor, NonInteractiveSeededFheBools, ParameterSelector, SampleExtractor,
+};
+use rayon::{ThreadPool, ThreadPoolBuilder};
+use rocket::serde::{Deserialize, Serialize};
+use std::cell::{Cell, RefCell};
+use std::fmt::{Debug, Display};
+use std::sync::{Arc, Once, OnceLock};
+
+type PzServerKeyShare = CommonReferenceSeededNonInteractiveMultiPartyServerKeyShare<
+    Vec<Vec<u64>>,
+    BoolParameters<u64>,
+    NonInteractiveMultiPartyCrs<Seed>,
+>;
+type PzServerKey = SeededNonInteractiveMultiPartyServerKey<
+    Vec<Vec<u64>>,
+    NonInteractiveMultiPartyCrs<Seed>,
+    BoolParameters<u64>,
+>;
+type PzEncryptedWord = NonInteractiveSeededFheBools<Vec<u64>, Seed>;
+
+/// The cryptography run by the server and its clients
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub enum Backend {
+    /// Non-interactive multi-party FHE
+    #[default]
+    PhantomZone,
+    /// Insecure, every bit travels in the clear. Lets tests run the whole flow in seconds
+    Mock,
+}
+
+impl Display for Backend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+/// The phantom-zone parameters, named by how many parties they support
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub enum ParameterSet {
+    LTE2Party,
+    #[default]
+    LTE4Party,
+    LTE8Party,
+}
+
+impl Display for ParameterSet {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+thread_local! {
+    /// The parameter set this thread was set up with, see [`Backend::init_thread`]
+    static PARAMETER_SET: Cell<Option<ParameterSet>> = const { Cell::new(None) };
+}
+
+/// The gate worker pools, one per parameter set, see [`ParameterSet::pool`]
+static POOLS: [OnceLock<ThreadPool>; 3] = [OnceLock::new(), OnceLock::new(), OnceLock::new()];
+
+impl ParameterSet {
+    const ALL: [Self; 3] = [Self::LTE2Party, Self::LTE4Party, Self::LTE8Party];
+
+    /// The parameter set of this thread, if it was set up for phantom-zone
+    pub(crate) fn active() -> Option<Self> {
+        PARAMETER_SET.get()
+    }
+
+    fn init_thread(&self) {
+        set_parameter_set(self.selector());
+        PARAMETER_SET.set(Some(*self));
+    }
+
+    /// The worker pool evaluating gates under this parameter set. Built on first use with its
+    /// threads set up for the parameter set, then shared by every circuit of the process
+    pub(crate) fn pool(&self) -> &'static ThreadPool {
+        let index = Self::ALL.iter().position(|p| p == self).expect("Listed");
+        let parameter_set = *self;
+        POOLS[index].get_or_init(|| {
+            ThreadPoolBuilder::new()
+                .thread_name(move |i| format!("gates-{parameter_set}-{i}"))
+                .start_handler(move |_| parameter_set.init_thread())
+                .build()
+                .expect("Worker threads spawn")
+        })
+    }
+
+    /// Run `f` on the pool of the parameter set this thread was set up with. Circuits install it
+    /// within a run that installed it already, which is cheap: a worker runs `f` in place
+    pub(crate) fn install<R: Send>(f: impl FnOnce() -> R + Send) -> R {
+        Self::active()
+            .expect("Set up the thread with Backend::init_thread")
+            .pool()
+            .install(f)
+    }
+
+    /// The smallest parameter set supporting `players`
+    pub fn for_players(players: usize) -> Option<Self> {
+        Self::ALL.into_iter().find(|p| players <= p.max_players())
+    }
+
+    pub fn max_players(&self) -> usize {
+        match self {
+            Self::LTE2Party => 2,
+            Self::LTE4Party => 4,
+            Self::LTE8Party => 8,
+        }
+    }
+
+    fn selector(&self) -> ParameterSelector {
+        match self {
+            Self::LTE2Party => ParameterSelector::NonInteractiveLTE2Party,
+            Self::LTE4Party => ParameterSelector::NonInteractiveLTE4Party,
+            Self::LTE8Party => ParameterSelector::NonInteractiveLTE8Party,
+        }
+    }
+}
+
+impl Backend {
+    /// Set up the thread for this backend with the common reference seed
+    pub(crate) fn setup(&self, parameter_set: ParameterSet, seed: &Seed) {
+        self.init_thread(parameter_set);
+        if let Backend::PhantomZone = self {
+            set_common_reference_seed(*seed);
+        }
+    }
+
+    /// Set the thread-local parameters, for threads other than the one that called `setup`
+    pub(crate) fn init_thread(&self, parameter_set: ParameterSet) {
+        match self {
+            Backend::PhantomZone => parameter_set.init_thread(),
+            Backend::Mock => {}
+        }
+    }
+
+    pub fn gen_client_key(&self) -> ClientKey {
+        match self {
+            Backend::PhantomZone => ClientKey::PhantomZone(phantom_zone::gen_client_key()),
+            Backend::Mock => ClientKey::Mock,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub enum ClientKey {
+    PhantomZone(phantom_zone::ClientKey),
+    Mock,
+}
+
+impl ClientKey {
+    pub fn backend(&self) -> Backend {
+        match self {
+            ClientKey::PhantomZone(_) => Backend::PhantomZone,
+            ClientKey::Mock => Backend::Mock,
+        }
+    }
+
+    pub fn gen_server_key_share(&self, user_id: UserId, total_users: usize) -> ServerKeyShare {
+        match self {
+            ClientKey::PhantomZone(ck) => ServerKeyShare::PhantomZone(Box::new(
+                phantom_zone::gen_server_key_share(user_id, total_users, ck),
+            )),
+            ClientKey::Mock => ServerKeyShare::Mock,
+        }
+    }
+
+    pub(crate) fn encrypt(&self, bits: &[bool]) -> EncryptedWord {
+        match self {
+            ClientKey::PhantomZone(ck) => {
+                let word: PzEncryptedWord = ck.encrypt(bits);
+                EncryptedWord::PhantomZone(word)
+            }
+            ClientKey::Mock => EncryptedWord::Mock(bits.to_vec()),
+        }
+    }
+
+    pub(crate) fn gen_decryption_share(&self, bit: &Ciphertext) -> u64 {
+        match (self, bit) {
+            (ClientKey::PhantomZone(ck), Ciphertext::PhantomZone(bit)) => {
+                ck.gen_decryption_share(bit)
+            }
+            (ClientKey::Mock, Ciphertext::Mock(_)) => 0,
+            _ => panic!("The key and the ciphertext are from different backends"),
+        }
+    }
+
+    pub(crate) fn aggregate_decryption_shares(&self, bit: &Ciphertext, shares: &[u64]) -> bool {
+        match (self, bit) {
+            (ClientKey::PhantomZone(ck), Ciphertext::PhantomZone(bit)) => {
+                ck.aggregate_decryption_shares(bit, shares)
+            }
+            (ClientKey::Mock, Ciphertext::Mock(bit)) => *bit,
+            _ => panic!("The key and the ciphertext are from different backends"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub enum ServerKeyShare {
+    PhantomZone(Box<PzServerKeyShare>),
+    Mock,
+}
+
+impl ServerKeyShare {
+    pub fn backend(&self) -> Backend {
+        match self {
+            ServerKeyShare::PhantomZone(_) => Backend::PhantomZone,
+            ServerKeyShare::Mock => Backend::Mock,
+        }
+    }
+}
+
+/// The server key of a game. It stays with its game, and is installed on the threads that
+/// evaluate the game's circuits
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub(crate) enum ServerKey {
+    PhantomZone(Arc<PzServerKey>),
+    Mock,
+}
+
+impl Debug for ServerKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ServerKey::PhantomZone(_) => write!(f, "ServerKey::PhantomZone"),
+            ServerKey::Mock => write!(f, "ServerKey::Mock"),
+        }
+    }
+}
+
+/// phantom-zone reads the server key from a slot that can only be set once per process.
+/// The first PhantomZone key claimed takes the slot, with its name in the key cache if it has
+/// one; mock games need none
+static PZ_SERVER_KEY: OnceLock<(Arc<PzServerKey>, Option<String>)> = OnceLock::new();
+static PZ_SERVER_KEY_SET: Once = Once::new();
+
+thread_local! {
+    /// The key of the game evaluated on this thread. phantom-zone reads its process-wide slot
+    /// anyway, so this only lets [`Ciphertext::install`] check the evaluation went through
+    /// [`ServerKey::scope`]
+    static SCOPED_SERVER_KEY: RefCell<Option<ServerKey>> = const { RefCell::new(None) };
+}
+
+/// Puts the outer key back when a scope ends, even if it panicked, as the thread gets reused
+struct ScopeGuard(Option<ServerKey>);
+
+impl Drop for ScopeGuard {
+    fn drop(&mut self) {
+        SCOPED_SERVER_KEY.set(self.0.take());
+    }
+}
+
+impl ServerKey {
+    /// Take the phantom-zone slot for this key, named `hash` in the key cache. Claiming the key
+    /// in the slot again, or a copy of it loaded from the cache, is fine and hands back the key
+    /// in the slot, so games sharing the key share one copy
+    pub(crate) fn claim(self, hash: Option<&str>) -> Result<Self, Error> {
+        match self {
+            ServerKey::PhantomZone(key) => {
+                let (claimed, claimed_hash) =
+                    PZ_SERVER_KEY.get_or_init(|| (key.clone(), hash.map(str::to_string)));
+                let cached_copy = hash.is_some() && claimed_hash.as_deref() == hash;
+                if Arc::ptr_eq(claimed, &key) || cached_copy {
+                    Ok(ServerKey::PhantomZone(claimed.clone()))
+                } else {
+                    Err(Error::ServerKeyTaken)
+                }
+            }
+            ServerKey::Mock => Ok(ServerKey::Mock),
+        }
+    }
+
+    /// Make the key available to the gates evaluated on the current thread
+    pub(crate) fn install(&self) {
+        match self {
+            ServerKey::PhantomZone(key) => {
+                let (owner, _) = PZ_SERVER_KEY.get().expect("Claimed by its game");
+                assert!(
+                    Arc::ptr_eq(owner, key),
+                    "Another game holds the phantom-zone server key"
+                );
+                PZ_SERVER_KEY_SET.call_once(|| key.set_server_key());
+            }
+            ServerKey::Mock => {}
+        }
+    }
+
+    /// Run `f` with the key installed for the gates it evaluates
+    pub(crate) fn scope<R>(&self, f: impl FnOnce() -> R) -> R {
+        self.install();
+        let _outer = ScopeGuard(SCOPED_SERVER_KEY.replace(Some(self.clone())));
+        f()
+    }
+
+    fn scoped() -> Option<Self> {
+        SCOPED_SERVER_KEY.with_borrow(|key| key.clone())
+    }
+
+    /// Whether the phantom-zone slot holds a key named in the key cache, which another game
+    /// submitting the same seed and shares could claim
+    pub(crate) fn is_cached_pz_key_held() -> bool {
+        PZ_SERVER_KEY.get().is_some_and(|(_, hash)| hash.is_some())
+    }
+}
+
+/// Aggregate the server key shares into the key of a game, to be claimed with
+/// [`ServerKey::claim`]. The thread must be set up with [`Backend::init_thread`]
+pub(crate) fn derive_server_key(
+    backend: Backend,
+    server_key_shares: &[ServerKeyShare],
+) -> Result<ServerKey, Error> {
+    match backend {
+        Backend::PhantomZone => {
+            if PZ_SERVER_KEY.get().is_some() {
+                return Err(Error::ServerKeyTaken);
+            }
+            let server_key_shares = server_key_shares
+                .iter()
+                .map(|share| match share {
+                    ServerKeyShare::PhantomZone(share) => (**share).clone(),
+                    ServerKeyShare::Mock => panic!("Got a mock server key share"),
+                })
+                .collect_vec();
+            let server_key = crate::time!(
+                || aggregate_server_key_shares(&server_key_shares),
+                "Aggregate server key shares"
+            );
+            Ok(ServerKey::PhantomZone(Arc::new(server_key)))
+        }
+        Backend::Mock => Ok(ServerKey::Mock),
+    }
+}
+
+/// A word encrypted by one user, before it is switched to the server key
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub enum EncryptedWord {
+    PhantomZone(PzEncryptedWord),
+    Mock(Vec<bool>),
+}
+
+impl EncryptedWord {
+    /// Switch to the server key. The thread must be set up with [`Backend::init_thread`]
+    /// and have the server key installed
+    pub(crate) fn unpack(&self, user_id: UserId) -> Word {
+        match self {
+            EncryptedWord::PhantomZone(word) => word
+                .unseed::<Vec<Vec<u64>>>()
+                .key_switch(user_id)
+                .extract_all()
+                .into_iter()
+                .map(Ciphertext::PhantomZone)
+                .collect_vec(),
+            EncryptedWord::Mock(bits) => bits.iter().copied().map(Ciphertext::Mock).collect_vec(),
+        }
+    }
+}
+
+/// A bit the server computes on
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub enum Ciphertext {
+    PhantomZone(FheBool),
+    Mock(bool),
+}
+
+impl Ciphertext {
+    fn map2(
+        &self,
+        rhs: &Self,
+        fhe: impl Fn(&FheBool, &FheBool) -> FheBool,
+        plain: impl Fn(&bool, &bool) -> bool,
+    ) -> Self {
+        match (self, rhs) {
+            (Self::PhantomZone(a), Self::PhantomZone(b)) => Self::PhantomZone(fhe(a, b)),
+            (Self::Mock(a), Self::Mock(b)) => Self::Mock(plain(a, b)),
+            _ => panic!("Can't mix ciphertexts from different backends"),
+        }
+    }
+}
+
+impl GateOps for Ciphertext {
+    fn and2(&self, rhs: &Self) -> Self {
+        self.map2(rhs, FheBool::and2, bool::and2)
+    }
+    fn nand2(&self, rhs: &Self) -> Self {
+        self.map2(rhs, FheBool::nand2, bool::nand2)
+    }
+    fn or2(&self, rhs: &Self) -> Self {
+        self.map2(rhs, FheBool::or2, bool::or2)
+    }
+    fn nor2(&self, rhs: &Self) -> Self {
+        self.map2(rhs, FheBool::nor2, bool::nor2)
+    }
+    fn xor2(&self, rhs: &Self) -> Self {
+        self.map2(rhs, FheBool::xor2, bool::xor2)
+    }
+    fn xnor2(&self, rhs: &Self) -> Self {
+        self.map2(rhs, FheBool::xnor2, bool::xnor2)
+    }
+    fn inv(&self) -> Self {
+        match self {
+            Self::PhantomZone(a) => Self::PhantomZone(a.inv()),
+            Self::Mock(a) => Self::Mock(a.inv()),
+        }
+    }
+    fn mux2(&self, a: &Self, b: &Self) -> Self {
+        match (self, a, b) {
+            (Self::PhantomZone(s), Self::PhantomZone(a), Self::PhantomZone(b)) => {
+                Self::PhantomZone(s.mux2(a, b))
+            }
+            (Self::Mock(s), Self::Mock(a), Self::Mock(b)) => Self::Mock(s.mux2(a, b)),
+            _ => panic!("Can't mix ciphertexts from different backends"),
+        }
+    }
+    fn constant(value: bool, like: &Self) -> Self {
+        match like {
+            Self::PhantomZone(like) => Self::PhantomZone(FheBool::constant(value, like)),
+            Self::Mock(like) => Self::Mock(bool::constant(value, like)),
+        }
+    }
+    /// The gates run on the pool of the parameter set of the calling thread. The phantom-zone
+    /// server key is process-wide, installed by [`ServerKey::scope`]
+    fn install<R: Send>(like: &Self, f: impl FnOnce() -> R + Send) -> R {
+        match like {
+            Self::PhantomZone(like) => {
+                assert!(
+                    ServerKey::scoped().is_some(),
+                    "Evaluate within ServerKey::scope"
+                );
+                FheBool::install(like, f)
+            }
+            Self::Mock(like) => bool::install(like, f),
+        }
+    }
+}
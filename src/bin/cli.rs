@@ -1,13 +1,15 @@
 use anyhow::{anyhow, bail, Error};
 use chickens::{
-    setup, CircuitOutput, DecryptionSharesMap, Direction, GameStateLocalView, ServerState, UserId,
-    WebClient, BOARD_SIZE,
+    binary_to_u32, setup, CircuitOutput, ClientKey, DecryptionSharesMap, Direction, GameConfig,
+    GameId, GameStateLocalView, ServerState, UserId, WebClient,
 };
-use clap::{command, Parser};
+use clap::Parser;
 use itertools::Itertools;
-use phantom_zone::{gen_client_key, gen_server_key_share, ClientKey};
 use rustyline::{error::ReadlineError, DefaultEditor};
-use std::{collections::HashMap, fmt::Display};
+use std::{collections::HashMap, fmt::Display, time::Duration};
+
+/// How long commands wait on the other players before handing back the prompt
+const WAIT_TIMEOUT: Duration = Duration::from_secs(60);
 
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
@@ -15,6 +17,8 @@ struct Cli2 {
     /// Optional name to operate on
     name: String,
     url: String,
+    /// Game to join. Creates a new game when left out
+    game: Option<GameId>,
 }
 
 enum State {
@@ -63,9 +67,11 @@ impl State {
                 format!("Hi {}, we just connected to server {}.", name, client.url())
             }
             State::Setup(StateSetup { .. }) => "✅ Setup completed!".to_string(),
-            State::ConcludedRegistration(_) => "✅ Got 4 players!".to_string(),
+            State::ConcludedRegistration(s) => format!("✅ Got {} players!", s.names.len()),
             State::SubmittedSks(_) => "✅ Server key share submitted!".to_string(),
-            State::ConcludedSubmitSks(_) => "✅ Got all 4 server key shares!".to_string(),
+            State::ConcludedSubmitSks(s) => {
+                format!("✅ Got all {} server key shares!", s.names.len())
+            }
             State::InitGame(_) => "✅ New game start!".to_string(),
             State::SetupGame(_) => "✅ Set starting coordinates!".to_string(),
             State::ConcludedSetupGame(_) => "✅ Ready for an action!".to_string(),
@@ -82,14 +88,19 @@ impl State {
     }
 
     fn print_instruction(&self) {
+        let init_game;
         let msg = match self {
-            State::Setup(_) => "We need 4 players. Enter `next` to check if we can proceed.",
+            State::Setup(_) => "Wait for other players to join. Enter `next` to check if we can proceed, or `start` to start the game with the players who joined.",
             State::SubmittedSks(_) =>
-                "Server needs to get all 4 server key shares. Enter `next` to check if we can proceed.",
+                "Server needs to get everyone's server key share. Enter `next` to check if we can proceed.",
             State::ConcludedSubmitSks(_) => "Enter `next` to start a new game.",
-            State::InitGame(_) => "Enter `next ${x} ${y}` with your starting coordinates (x, y).\n The board is 4 x 4, so x, y has to be in the range [0, 3]." ,
+            State::InitGame(s) => {
+                let dim = s.view.board_dim();
+                init_game = format!("Enter `next ${{x}} ${{y}}` with your starting coordinates (x, y).\n The board is {dim} x {dim}, so x, y has to be in the range [0, {}].", dim - 1);
+                &init_game
+            }
             State::SetupGame(_) => "Wait for every user to set starting coordinates. Enter `next` to check if we can proceed.",
-            State::ConcludedSetupGame(_) => "Enter one of the commands {`move up` | `move down` | `move left` | `move right` | `lay` | `pickup`}",
+            State::ConcludedSetupGame(_) => "Enter one of the commands {`move up` | `move down` | `move left` | `move right` | `lay` | `pickup` | `add ${n}` | `view`}",
             State::GameAction(_) => "Server running FHE. Enter `next` to check if it completed",
             State::DownloadedOutput(_) => "Wait for other players to submit decryption shares. Enter `next` to check if we can proceed.",
             State::Decrypted(_) => "Enter `next` to take another action; Or exit with `CTRL-D`",
@@ -110,6 +121,7 @@ struct StateSetup {
     client: WebClient,
     ck: ClientKey,
     user_id: UserId,
+    board_dim: usize,
 }
 
 struct Registration {
@@ -117,6 +129,7 @@ struct Registration {
     client: WebClient,
     ck: ClientKey,
     user_id: UserId,
+    board_dim: usize,
     names: Vec<String>,
 }
 
@@ -137,7 +150,6 @@ struct StateGameAction {
     user_id: UserId,
     names: Vec<String>,
     view: GameStateLocalView,
-    is_my_action: bool, // whether I took the action, or other players took the action
     round: usize,
 }
 
@@ -151,7 +163,6 @@ struct StateDownloadedOutput {
     fhe_out: CircuitOutput,
     shares: DecryptionSharesMap,
     view: GameStateLocalView,
-    is_my_action: bool, // only decrypt if it is my action
     round: usize,
 }
 
@@ -163,8 +174,6 @@ struct StateDecrypted {
     user_id: UserId,
     names: Vec<String>,
     view: GameStateLocalView,
-    is_my_action: bool,
-    decrypted_output: Option<Vec<bool>>,
     round: usize,
 }
 
@@ -175,7 +184,22 @@ async fn main() {
     let url: String = cli.url;
 
     let mut rl = DefaultEditor::new().unwrap();
-    let client = WebClient::new(&url);
+    let mut client = WebClient::new(&url);
+    let game_id = match cli.game {
+        Some(game_id) => game_id,
+        None => match client.create_game(&GameConfig::default()).await {
+            Ok(game_id) => {
+                println!("Created game #{game_id}, share the ID with the other players");
+                game_id
+            }
+            Err(err) => {
+                println!("❌ Error: {:?}", err);
+                return;
+            }
+        },
+    };
+    client.join_game(game_id);
+    println!("Joined game #{game_id}");
     let mut state = State::Init(StateInit { name, client });
     println!("{}", state);
     state.print_status_update();
@@ -215,19 +239,22 @@ async fn main() {
     }
 }
 
-async fn cmd_setup(name: &str, client: &WebClient) -> Result<(ClientKey, usize), Error> {
-    let seed = client.get_seed().await?;
+async fn cmd_setup(name: &str, client: &WebClient) -> Result<(ClientKey, usize, usize), Error> {
+    let param = client.get_param().await?;
     println!(
         "Acquired seed for commen reference string (CRS) 0x{}",
-        hex::encode(seed)
+        hex::encode(param.seed)
     );
-    println!("Setup my CRS");
-    setup(&seed);
+    println!(
+        "Setup my CRS for the {} backend with {}",
+        param.backend, param.parameter_set
+    );
+    setup(&param);
     println!("Generate my client key");
-    let ck = gen_client_key();
+    let ck = param.backend.gen_client_key();
     let user = client.register(name).await?;
     println!("Hi {}, you are registered with ID: {}", user.name, user.id);
-    Ok((ck, user.id))
+    Ok((ck, user.id, param.board_dim))
 }
 
 async fn cmd_get_names(client: &WebClient) -> Result<(bool, Vec<String>), Error> {
@@ -236,23 +263,40 @@ async fn cmd_get_names(client: &WebClient) -> Result<(bool, Vec<String>), Error>
     Ok((d.is_concluded(), d.get_names()))
 }
 
-async fn cmd_submit_sks(client: &WebClient, ck: &ClientKey, user_id: &UserId) -> Result<(), Error> {
-    let total_users = 4;
+async fn cmd_start_game(client: &WebClient, user_id: UserId) -> Result<(bool, Vec<String>), Error> {
+    client.start_game(user_id).await?;
+    cmd_get_names(client).await
+}
+
+async fn cmd_submit_sks(
+    client: &WebClient,
+    ck: &ClientKey,
+    user_id: &UserId,
+    total_users: usize,
+) -> Result<(), Error> {
     println!("Generating server key share");
-    let sks = gen_server_key_share(*user_id, total_users, ck);
+    let sks = ck.gen_server_key_share(*user_id, total_users);
     println!("Submit server key share");
     client.submit_sks(*user_id, &sks).await?;
     Ok(())
 }
 
 async fn cmd_check_submit_sks_complete(client: &WebClient) -> Result<bool, Error> {
-    let d = client.get_dashboard().await?;
+    println!("Waiting for the other server key shares ...");
+    let d = client
+        .wait_for_state(ServerState::ReadyForSetupGame, WAIT_TIMEOUT)
+        .await?;
     d.print_presentation();
     Ok(d.is_submit_sks_complete())
 }
 
-async fn cmd_init_game(client: &WebClient, ck: &ClientKey, user_id: UserId) -> Result<(), Error> {
-    let initial_eggs = [false; BOARD_SIZE];
+async fn cmd_init_game(
+    client: &WebClient,
+    ck: &ClientKey,
+    user_id: UserId,
+    board_dim: usize,
+) -> Result<(), Error> {
+    let initial_eggs = vec![false; board_dim * board_dim];
     client.init_game(ck, user_id, &initial_eggs).await?;
     Ok(())
 }
@@ -262,9 +306,10 @@ async fn cmd_setup_game(
     client: &WebClient,
     ck: &ClientKey,
     user_id: UserId,
+    board_dim: usize,
 ) -> Result<GameStateLocalView, Error> {
     let x = args
-        .get(0)
+        .first()
         .ok_or_else(|| anyhow!("please add init x coordinate"))?
         .parse::<u8>()?;
     let y = args
@@ -272,21 +317,31 @@ async fn cmd_setup_game(
         .ok_or_else(|| anyhow!("please add init y coordinate"))?
         .parse::<u8>()?;
 
-    if x > 3 {
-        return Err(anyhow!("init x coordinate has to be in the range [0, 3]"));
+    let max = board_dim - 1;
+    if x as usize > max {
+        return Err(anyhow!(
+            "init x coordinate has to be in the range [0, {max}]"
+        ));
     }
-    if y > 3 {
-        return Err(anyhow!("init y coordinate has to be in the range [0, 3]"));
+    if y as usize > max {
+        return Err(anyhow!(
+            "init y coordinate has to be in the range [0, {max}]"
+        ));
     }
 
-    let view = GameStateLocalView::new(x, y, user_id);
-    client.set_starting_coords(ck, user_id, &(x, y)).await?;
+    let view = GameStateLocalView::new(x, y, user_id, board_dim);
+    client
+        .set_starting_coords(ck, user_id, &(x, y), board_dim)
+        .await?;
     view.print();
     Ok(view)
 }
 
 async fn cmd_setup_game_complete(client: &WebClient) -> Result<bool, Error> {
-    let d = client.get_dashboard().await?;
+    println!("Waiting for the other starting coordinates ...");
+    let d = client
+        .wait_for_state(ServerState::ReadyForActions, WAIT_TIMEOUT)
+        .await?;
     d.print_presentation();
     Ok(d.is_setup_game_complete())
 }
@@ -299,7 +354,7 @@ async fn cmd_move(
     view: &GameStateLocalView,
 ) -> Result<GameStateLocalView, Error> {
     let arg = args
-        .get(0)
+        .first()
         .ok_or_else(|| anyhow!("please add direction to move"))?;
     let direction = match *arg {
         "up" => Direction::Up,
@@ -343,6 +398,34 @@ async fn cmd_pickup(
     Ok(view)
 }
 
+async fn cmd_add(
+    args: &[&str],
+    client: &WebClient,
+    ck: &ClientKey,
+    user_id: UserId,
+    view: &GameStateLocalView,
+) -> Result<GameStateLocalView, Error> {
+    let user_int = args
+        .first()
+        .ok_or_else(|| anyhow!("please add the integer to add"))?
+        .parse::<u32>()?;
+
+    client.add_int(ck, user_id, user_int).await?;
+    println!("Adding {} to the shared integer", user_int);
+    trigger_run(client, user_id).await?;
+    Ok(view.clone())
+}
+
+async fn cmd_view(
+    client: &WebClient,
+    user_id: UserId,
+    view: &GameStateLocalView,
+) -> Result<GameStateLocalView, Error> {
+    client.view_int(user_id).await?;
+    trigger_run(client, user_id).await?;
+    Ok(view.clone())
+}
+
 async fn trigger_run(client: &WebClient, user_id: UserId) -> Result<(), Error> {
     println!("Requesting FHE run ...");
     let resp = client.trigger_fhe_run(user_id).await?;
@@ -351,10 +434,17 @@ async fn trigger_run(client: &WebClient, user_id: UserId) -> Result<(), Error> {
 }
 
 async fn cmd_fhe_complete(client: &WebClient) -> Result<bool, Error> {
-    let d = client.get_dashboard().await?;
+    println!("Waiting for the FHE run ...");
+    let d = client.follow_fhe_run().await?;
     Ok(d.is_fhe_complete())
 }
 
+/// Why the last FHE run failed, if it did
+async fn cmd_fhe_failure(client: &WebClient) -> Result<Option<String>, Error> {
+    let d = client.get_dashboard().await?;
+    Ok(d.get_fhe_failure().map(str::to_string))
+}
+
 async fn cmd_fhe_ongoing(client: &WebClient) -> Result<bool, Error> {
     let d = client.get_dashboard().await?;
     Ok(d.is_fhe_ongoing())
@@ -364,8 +454,6 @@ async fn cmd_download_output(
     client: &WebClient,
     user_id: &UserId,
     ck: &ClientKey,
-    should_submit_shares: bool,
-    round: usize,
 ) -> Result<(CircuitOutput, DecryptionSharesMap), Error> {
     let resp = client.trigger_fhe_run(*user_id).await?;
     if !matches!(resp, ServerState::CompletedFhe) {
@@ -376,17 +464,19 @@ async fn cmd_download_output(
     let fhe_out = client.get_fhe_output().await?;
 
     println!("Generating my decrypting shares");
+    // The share of my output never leaves, so only I can decrypt it
     let mut shares = HashMap::new();
-    let my_decryption_share = fhe_out.gen_decryption_share(ck);
-    shares.insert((round, *user_id), my_decryption_share.clone());
-
-    if should_submit_shares {
-        println!("Submitting my decrypting shares");
-        client
-            .submit_decryption_share(*user_id, &(round, my_decryption_share))
-            .await?;
+    for output in fhe_out.received_by(*user_id) {
+        let my_decryption_share = fhe_out
+            .gen_decryption_share(ck, &output.name)
+            .expect("Output exists");
+        shares.insert((output.name.clone(), *user_id), my_decryption_share);
     }
 
+    println!("Submitting my decrypting shares of the other outputs");
+    let others = fhe_out.gen_decryption_shares(ck, *user_id);
+    client.submit_decryption_shares(*user_id, &others).await?;
+
     Ok((fhe_out, shares))
 }
 
@@ -401,29 +491,41 @@ async fn cmd_decryption_submission_completed(
 
 async fn cmd_download_shares(
     client: &WebClient,
+    user_id: UserId,
     names: &[String],
     ck: &ClientKey,
     shares: &mut DecryptionSharesMap,
     co: &CircuitOutput,
     view: &GameStateLocalView,
-    round: usize,
-) -> Result<Vec<bool>, Error> {
+) -> Result<(), Error> {
     let total_users = names.len();
-    println!("Acquiring decryption shares needed");
-    for user_id in 0..total_users {
-        if shares.get(&(round, user_id)).is_none() {
-            let ds = client.get_decryption_share(round, user_id).await?;
-            shares.insert((round, user_id), ds);
+    for output in co.received_by(user_id) {
+        println!("Acquiring decryption shares of {}", output.name);
+        for other in 0..total_users {
+            let key = (output.name.clone(), other);
+            if shares.get(&key).is_none() {
+                let ds = client.get_decryption_share(&output.name, other).await?;
+                shares.insert(key, ds);
+            }
+        }
+        println!("Decrypt my encrypted {}", output.name);
+        let dss = (0..total_users)
+            .map(|other| {
+                shares
+                    .get(&(output.name.clone(), other))
+                    .expect("exists")
+                    .to_owned()
+            })
+            .collect_vec();
+        let decrypted_output = co.decrypt(ck, &output.name, &dss).expect("Output exists");
+        println!("Final decrypted {}: {:?}", output.name, decrypted_output);
+        if output.name == CircuitOutput::int_name(user_id) {
+            println!("🔢 Shared integer: {}", binary_to_u32(&decrypted_output));
+        } else {
+            view.print_with_output(&decrypted_output);
         }
     }
-    println!("Decrypt the encrypted output");
-    let dss = (0..total_users)
-        .map(|user_id| shares.get(&(round, user_id)).expect("exists").to_owned())
-        .collect_vec();
-    let decrypted_output = co.decrypt(ck, &dss);
-    println!("Final decrypted output: {:?}", decrypted_output);
-    view.print_with_output(&decrypted_output);
-    Ok(decrypted_output)
+    Ok(())
 }
 
 async fn cmd_done(client: &WebClient, user_id: UserId) -> Result<(), Error> {
@@ -432,10 +534,56 @@ async fn cmd_done(client: &WebClient, user_id: UserId) -> Result<(), Error> {
 }
 
 async fn cmd_ready_for_actions(client: &WebClient, round: usize) -> Result<bool, Error> {
-    let d = client.get_dashboard().await?;
+    println!("Waiting for the other players to finish the round ...");
+    let d = client.wait_for_round(round + 1, WAIT_TIMEOUT).await?;
     Ok(d.is_ready_for_actions(round))
 }
 
+/// Move on to [`State::GameAction`] after requesting an action.
+/// If another player took an action first, we help decrypt their output instead.
+async fn after_action(
+    result: Result<GameStateLocalView, Error>,
+    s: StateGame,
+) -> Result<State, (Error, State)> {
+    match result {
+        Ok(view) => Ok(State::GameAction(StateGameAction {
+            view,
+            name: s.name,
+            client: s.client,
+            ck: s.ck,
+            user_id: s.user_id,
+            names: s.names,
+            round: s.round,
+        })),
+        Err(err) => {
+            if err.to_string().contains("Wrong server state") {
+                let result = match cmd_fhe_ongoing(&s.client).await {
+                    Ok(is_ongoing) => {
+                        if is_ongoing {
+                            println!("❌ Your action DID NOT take effect!");
+                            println!("❗ Another player took an action first. Let's decrypt their output first.");
+                            Ok(State::GameAction(StateGameAction {
+                                name: s.name,
+                                client: s.client,
+                                ck: s.ck,
+                                user_id: s.user_id,
+                                names: s.names,
+                                view: s.view,
+                                round: s.round,
+                            }))
+                        } else {
+                            Err((err, State::ConcludedSetupGame(s)))
+                        }
+                    }
+                    Err(err) => Err((err, State::ConcludedSetupGame(s))),
+                };
+                return result;
+            }
+            Err((err, State::ConcludedSetupGame(s)))
+        }
+    }
+}
+
 async fn run(state: State, line: &str) -> Result<State, (Error, State)> {
     let terms: Vec<&str> = line.split_whitespace().collect();
     if terms.is_empty() {
@@ -446,11 +594,12 @@ async fn run(state: State, line: &str) -> Result<State, (Error, State)> {
     if cmd == &"next" {
         match state {
             State::Init(s) => match cmd_setup(&s.name, &s.client).await {
-                Ok((ck, user_id)) => Ok(State::Setup(StateSetup {
+                Ok((ck, user_id, board_dim)) => Ok(State::Setup(StateSetup {
                     name: s.name,
                     client: s.client,
                     ck,
                     user_id,
+                    board_dim,
                 })),
                 Err(err) => Err((err, State::Init(s))),
             },
@@ -462,6 +611,7 @@ async fn run(state: State, line: &str) -> Result<State, (Error, State)> {
                             client: s.client,
                             ck: s.ck,
                             user_id: s.user_id,
+                            board_dim: s.board_dim,
                             names,
                         }))
                     } else {
@@ -471,7 +621,7 @@ async fn run(state: State, line: &str) -> Result<State, (Error, State)> {
                 Err(err) => Err((err, State::Setup(s))),
             },
             State::ConcludedRegistration(s) => {
-                match cmd_submit_sks(&s.client, &s.ck, &s.user_id).await {
+                match cmd_submit_sks(&s.client, &s.ck, &s.user_id, s.names.len()).await {
                     Ok(()) => Ok(State::SubmittedSks(s)),
                     Err(err) => Err((err, State::ConcludedRegistration(s))),
                 }
@@ -487,23 +637,25 @@ async fn run(state: State, line: &str) -> Result<State, (Error, State)> {
                 Err(err) => Err((err, State::SubmittedSks(s))),
             },
             State::ConcludedSubmitSks(s) => {
-                match cmd_init_game(&s.client, &s.ck, s.user_id).await {
+                match cmd_init_game(&s.client, &s.ck, s.user_id, s.board_dim).await {
                     Ok(()) => Ok(State::InitGame(StateGame {
                         name: s.name,
                         client: s.client,
                         ck: s.ck,
                         user_id: s.user_id,
                         names: s.names,
-                        view: GameStateLocalView::new(0, 0, s.user_id),
+                        view: GameStateLocalView::new(0, 0, s.user_id, s.board_dim),
                         round: 0,
                     })),
                     Err(err) => Err((err, State::ConcludedSubmitSks(s))),
                 }
             }
-            State::InitGame(s) => match cmd_setup_game(args, &s.client, &s.ck, s.user_id).await {
-                Ok(view) => Ok(State::SetupGame(StateGame { view, ..s })),
-                Err(err) => Err((err, State::InitGame(s))),
-            },
+            State::InitGame(s) => {
+                match cmd_setup_game(args, &s.client, &s.ck, s.user_id, s.view.board_dim()).await {
+                    Ok(view) => Ok(State::SetupGame(StateGame { view, ..s })),
+                    Err(err) => Err((err, State::InitGame(s))),
+                }
+            }
             State::SetupGame(s) => match cmd_setup_game_complete(&s.client).await {
                 Ok(is_complete) => {
                     if is_complete {
@@ -523,12 +675,25 @@ async fn run(state: State, line: &str) -> Result<State, (Error, State)> {
                         Ok(State::GameAction(s))
                     }
                 }
-                Err(err) => Err((err, State::GameAction(s))),
+                Err(err) => match cmd_fhe_failure(&s.client).await {
+                    // The round is open again, back to taking an action
+                    Ok(Some(reason)) => Err((
+                        anyhow!("The FHE run failed: {reason}"),
+                        State::ConcludedSetupGame(StateGame {
+                            name: s.name,
+                            client: s.client,
+                            ck: s.ck,
+                            user_id: s.user_id,
+                            names: s.names,
+                            view: s.view,
+                            round: s.round,
+                        }),
+                    )),
+                    _ => Err((err, State::GameAction(s))),
+                },
             },
             State::CompletedFhe(s) => {
-                match cmd_download_output(&s.client, &s.user_id, &s.ck, !s.is_my_action, s.round)
-                    .await
-                {
+                match cmd_download_output(&s.client, &s.user_id, &s.ck).await {
                     Ok((fhe_out, shares)) => Ok(State::DownloadedOutput(StateDownloadedOutput {
                         name: s.name,
                         client: s.client,
@@ -538,26 +703,12 @@ async fn run(state: State, line: &str) -> Result<State, (Error, State)> {
                         fhe_out,
                         shares,
                         view: s.view,
-                        is_my_action: s.is_my_action,
                         round: s.round,
                     })),
                     Err(err) => Err((err, State::CompletedFhe(s))),
                 }
             }
             State::DownloadedOutput(s) => {
-                if !s.is_my_action {
-                    return Ok(State::Decrypted(StateDecrypted {
-                        name: s.name,
-                        client: s.client,
-                        ck: s.ck,
-                        user_id: s.user_id,
-                        names: s.names,
-                        view: s.view,
-                        decrypted_output: None,
-                        is_my_action: false,
-                        round: s.round,
-                    }));
-                }
                 match cmd_decryption_submission_completed(&s.client, &s.user_id).await {
                     Ok(is_complete) => {
                         if is_complete {
@@ -573,24 +724,22 @@ async fn run(state: State, line: &str) -> Result<State, (Error, State)> {
             State::ConcludedDecryptionSubmission(mut s) => {
                 match cmd_download_shares(
                     &s.client,
+                    s.user_id,
                     &s.names,
                     &s.ck,
                     &mut s.shares,
                     &s.fhe_out,
                     &s.view,
-                    s.round,
                 )
                 .await
                 {
-                    Ok(decrypted_output) => Ok(State::Decrypted(StateDecrypted {
+                    Ok(()) => Ok(State::Decrypted(StateDecrypted {
                         name: s.name,
                         client: s.client,
                         ck: s.ck,
                         user_id: s.user_id,
                         names: s.names,
-                        decrypted_output: Some(decrypted_output),
                         view: s.view,
-                        is_my_action: true,
                         round: s.round,
                     })),
                     Err(err) => Err((err, State::DownloadedOutput(s))),
@@ -622,135 +771,59 @@ async fn run(state: State, line: &str) -> Result<State, (Error, State)> {
                 Err(err) => Err((err, State::NewRound(s))),
             },
         }
+    } else if cmd == &"start" {
+        match state {
+            State::Setup(s) => match cmd_start_game(&s.client, s.user_id).await {
+                Ok((_, names)) => Ok(State::ConcludedRegistration(Registration {
+                    name: s.name,
+                    client: s.client,
+                    ck: s.ck,
+                    user_id: s.user_id,
+                    board_dim: s.board_dim,
+                    names,
+                })),
+                Err(err) => Err((err, State::Setup(s))),
+            },
+            _ => Err((anyhow!("Invalid state for command {}", cmd), state)),
+        }
     } else if cmd == &"move" {
         match state {
             State::ConcludedSetupGame(s) => {
-                match cmd_move(args, &s.client, &s.ck, s.user_id, &s.view).await {
-                    Ok(view) => Ok(State::GameAction(StateGameAction {
-                        view,
-                        is_my_action: true,
-                        name: s.name,
-                        client: s.client,
-                        ck: s.ck,
-                        user_id: s.user_id,
-                        names: s.names,
-                        round: s.round,
-                    })),
-                    Err(err) => {
-                        if err.to_string().contains("Wrong server state") {
-                            let result = match cmd_fhe_ongoing(&s.client).await {
-                                Ok(is_ongoing) => {
-                                    if is_ongoing {
-                                        println!("❌ Your action DID NOT take effect!");
-                                        println!("❗ Another player took an action first. Let's decrypt their output first.");
-                                        Ok(State::GameAction(StateGameAction {
-                                            is_my_action: false,
-                                            name: s.name,
-                                            client: s.client,
-                                            ck: s.ck,
-                                            user_id: s.user_id,
-                                            names: s.names,
-                                            view: s.view,
-                                            round: s.round,
-                                        }))
-                                    } else {
-                                        Err((err, State::ConcludedSetupGame(s)))
-                                    }
-                                }
-                                Err(err) => Err((err, State::ConcludedSetupGame(s))),
-                            };
-                            return result;
-                        }
-                        Err((err, State::ConcludedSetupGame(s)))
-                    }
-                }
+                let result = cmd_move(args, &s.client, &s.ck, s.user_id, &s.view).await;
+                after_action(result, s).await
             }
             _ => Err((anyhow!("Invalid state for command {}", cmd), state)),
         }
     } else if cmd == &"lay" {
         match state {
-            State::ConcludedSetupGame(s) => match cmd_lay(&s.client, s.user_id, &s.view).await {
-                Ok(view) => Ok(State::GameAction(StateGameAction {
-                    view,
-                    is_my_action: true,
-                    name: s.name,
-                    client: s.client,
-                    ck: s.ck,
-                    user_id: s.user_id,
-                    names: s.names,
-                    round: s.round,
-                })),
-                Err(err) => {
-                    if err.to_string().contains("Wrong server state") {
-                        let result = match cmd_fhe_ongoing(&s.client).await {
-                            Ok(is_ongoing) => {
-                                if is_ongoing {
-                                    println!("❌ Your action DID NOT take effect!");
-                                    println!("❗ Another player took an action first. Let's decrypt their output first.");
-                                    Ok(State::GameAction(StateGameAction {
-                                        is_my_action: false,
-                                        name: s.name,
-                                        client: s.client,
-                                        ck: s.ck,
-                                        user_id: s.user_id,
-                                        names: s.names,
-                                        view: s.view,
-                                        round: s.round,
-                                    }))
-                                } else {
-                                    Err((err, State::ConcludedSetupGame(s)))
-                                }
-                            }
-                            Err(err) => Err((err, State::ConcludedSetupGame(s))),
-                        };
-                        return result;
-                    }
-                    Err((err, State::ConcludedSetupGame(s)))
-                }
-            },
+            State::ConcludedSetupGame(s) => {
+                let result = cmd_lay(&s.client, s.user_id, &s.view).await;
+                after_action(result, s).await
+            }
             _ => Err((anyhow!("Invalid state for command {}", cmd), state)),
         }
     } else if cmd == &"pickup" {
         match state {
-            State::ConcludedSetupGame(s) => match cmd_pickup(&s.client, s.user_id, &s.view).await {
-                Ok(view) => Ok(State::GameAction(StateGameAction {
-                    view,
-                    is_my_action: true,
-                    name: s.name,
-                    client: s.client,
-                    ck: s.ck,
-                    user_id: s.user_id,
-                    names: s.names,
-                    round: s.round,
-                })),
-                Err(err) => {
-                    if err.to_string().contains("Wrong server state") {
-                        let result = match cmd_fhe_ongoing(&s.client).await {
-                            Ok(is_ongoing) => {
-                                if is_ongoing {
-                                    println!("❌ Your action DID NOT take effect!");
-                                    println!("❗ Another player took an action first. Let's decrypt their output first.");
-                                    Ok(State::GameAction(StateGameAction {
-                                        is_my_action: false,
-                                        name: s.name,
-                                        client: s.client,
-                                        ck: s.ck,
-                                        user_id: s.user_id,
-                                        names: s.names,
-                                        view: s.view,
-                                        round: s.round,
-                                    }))
-                                } else {
-                                    Err((err, State::ConcludedSetupGame(s)))
-                                }
-                            }
-                            Err(err) => Err((err, State::ConcludedSetupGame(s))),
-                        };
-                        return result;
-                    }
-                    Err((err, State::ConcludedSetupGame(s)))
-                }
-            },
+            State::ConcludedSetupGame(s) => {
+                let result = cmd_pickup(&s.client, s.user_id, &s.view).await;
+                after_action(result, s).await
+            }
+            _ => Err((anyhow!("Invalid state for command {}", cmd), state)),
+        }
+    } else if cmd == &"add" {
+        match state {
+            State::ConcludedSetupGame(s) => {
+                let result = cmd_add(args, &s.client, &s.ck, s.user_id, &s.view).await;
+                after_action(result, s).await
+            }
+            _ => Err((anyhow!("Invalid state for command {}", cmd), state)),
+        }
+    } else if cmd == &"view" {
+        match state {
+            State::ConcludedSetupGame(s) => {
+                let result = cmd_view(&s.client, s.user_id, &s.view).await;
+                after_action(result, s).await
+            }
             _ => Err((anyhow!("Invalid state for command {}", cmd), state)),
         }
     } else if cmd == &"status" {
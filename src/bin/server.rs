@@ -1,7 +1,7 @@
 use chickens::rocket;
 
 #[rocket::main]
-async fn main() -> Result<(), rocket::Error> {
+async fn main() -> Result<(), Box<rocket::Error>> {
     rocket().launch().await?;
     Ok(())
 }
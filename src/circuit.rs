@@ -1,23 +1,12 @@
 use crate::{
-    compiled::{get_cell, lay_egg, move_player, pickup_egg, add_to_int},
-    time,
-    types::{GameStateEnc, ServerKeyShare, Word},
+    compiled::{
+        add_to_int, add_to_int_gates, get_cell, get_cell_gates, lay_egg, lay_egg_gates,
+        move_player, move_player_gates, pickup_egg, pickup_egg_gates,
+    },
+    types::{CircuitOutput, GameStateEnc, NamedOutput, Word},
     UserAction, UserId,
 };
 use itertools::Itertools;
-use phantom_zone::{aggregate_server_key_shares, set_parameter_set, ParameterSelector};
-
-pub const PARAMETER: ParameterSelector = ParameterSelector::NonInteractiveLTE4Party;
-
-/// Server work
-/// Warning: global variable change
-pub(crate) fn derive_server_key(server_key_shares: &[ServerKeyShare]) {
-    let server_key = time!(
-        || aggregate_server_key_shares(server_key_shares),
-        "Aggregate server key shares"
-    );
-    server_key.set_server_key();
-}
 
 pub(crate) fn evaluate_circuit(
     state: GameStateEnc,
@@ -25,8 +14,11 @@ pub(crate) fn evaluate_circuit(
 ) -> GameStateEnc {
     let mut state = state.clone();
     for (user_id, ua) in uas {
-        println!("Apply action {} for user {}", ua, user_id);
-        state = apply_action(state, *user_id, ua);
+        // Timed so the cost of each action shows in the logs
+        state = crate::time!(
+            || apply_action(state, *user_id, ua),
+            format!("Apply action {} for user {}", ua, user_id)
+        );
     }
     state
 }
@@ -37,31 +29,37 @@ pub(crate) fn apply_action(
     ua: &UserAction<Word>,
 ) -> GameStateEnc {
     let mut next_state = state.clone();
-    set_parameter_set(PARAMETER);
     match ua {
         UserAction::AddInt { user_int } => {
-            // happening on server side
-            // TODO: pass arg
-            // ?? = add_to_int(&user_int)
-            // println!("Adding integer: {}", user_int);
+            next_state.int = add_to_int(user_int, &state.int);
         }
         UserAction::MovePlayer { direction } => {
             next_state.coords[user_id] = Some(move_player(
-                &state.coords[user_id].as_ref().expect("exist"),
-                &direction,
+                state.board_dim,
+                state.coords[user_id].as_ref().expect("exist"),
+                direction,
             ));
         }
         UserAction::LayEgg => {
-            next_state.eggs = lay_egg(&state.coords[user_id].as_ref().expect("exist"), &state.eggs);
+            next_state.eggs = lay_egg(
+                state.board_dim,
+                state.coords[user_id].as_ref().expect("exist"),
+                &state.eggs,
+            );
         }
         UserAction::PickupEgg => {
-            next_state.eggs =
-                pickup_egg(&state.coords[user_id].as_ref().expect("exist"), &state.eggs);
+            next_state.eggs = pickup_egg(
+                state.board_dim,
+                state.coords[user_id].as_ref().expect("exist"),
+                &state.eggs,
+            );
+        }
+        UserAction::ViewInt => {
+            // Nothing to update, the accumulator is revealed in the circuit output
         }
         UserAction::InitGame { .. }
         | UserAction::SetStartingCoord { .. }
-        | UserAction::GetCell { .. }
-        | UserAction::ViewInt { .. }
+        | UserAction::GetCell
         | UserAction::Done => {
             unreachable!("Shouldn't be in the action queue")
         }
@@ -69,13 +67,61 @@ pub(crate) fn apply_action(
     next_state
 }
 
+/// Gates evaluated to apply `ua`
+pub(crate) fn action_gates<T>(board_dim: usize, ua: &UserAction<T>) -> usize {
+    match ua {
+        UserAction::AddInt { .. } => add_to_int_gates(),
+        UserAction::MovePlayer { .. } => move_player_gates(board_dim),
+        UserAction::LayEgg => lay_egg_gates(board_dim),
+        UserAction::PickupEgg => pickup_egg_gates(board_dim),
+        UserAction::ViewInt
+        | UserAction::InitGame { .. }
+        | UserAction::SetStartingCoord { .. }
+        | UserAction::GetCell
+        | UserAction::Done => 0,
+    }
+}
+
+/// Gates evaluated to get the outputs of `total_users` users
+pub(crate) fn output_gates(board_dim: usize, total_users: usize) -> usize {
+    total_users * get_cell_gates(board_dim, total_users)
+}
+
+/// Which players stand on the cell of `user_id`, one bit per player, then whether it has an egg
 pub(crate) fn get_user_cell(state: &GameStateEnc, user_id: UserId) -> Word {
     println!("Get cell for user {}", user_id);
-    set_parameter_set(PARAMETER);
     let coords = state.coords.iter().flatten().cloned().collect_vec();
     get_cell(
+        state.board_dim,
         &coords[user_id],
         &state.eggs,
         &coords.iter().flatten().cloned().collect_vec(),
     )
 }
+
+/// The words for `user_id` to decrypt after the run: their cell, and the shared integer if
+/// they asked to view it
+pub(crate) fn get_outputs(
+    state: &GameStateEnc,
+    uas: &[(UserId, UserAction<Word>)],
+    user_id: UserId,
+) -> Vec<NamedOutput> {
+    let cell = get_user_cell(state, user_id);
+    let mut outputs = vec![NamedOutput::new(
+        CircuitOutput::cell_name(user_id),
+        user_id,
+        cell,
+    )];
+    if uas
+        .iter()
+        .any(|(actor, ua)| *actor == user_id && matches!(ua, UserAction::ViewInt))
+    {
+        println!("Get shared integer");
+        outputs.push(NamedOutput::new(
+            CircuitOutput::int_name(user_id),
+            user_id,
+            state.int.clone(),
+        ));
+    }
+    outputs
+}
@@ -1,79 +1,229 @@
 use crate::{
-    dashboard::{Dashboard, RegisteredUser},
+    dashboard::{Dashboard, RegisteredUser, Registration},
     types::{
-        AnnotatedDecryptionShare, CircuitOutput, DecryptionShare, DecryptionShareSubmission,
-        EncryptedWord, Seed, ServerKeyShare, ServerState, SksSubmission, UserAction, UserId,
+        AnnotatedDecryptionShare, CircuitOutput, Compression, DecryptionShare,
+        DecryptionShareSubmission, EncryptedWord, GameConfig, GameId, Param, ServerKeyShare,
+        ServerState, SksUploadStart, UserAction, UserId,
     },
     ClientKey, Direction,
 };
-use anyhow::{anyhow, bail, Error};
+use anyhow::{anyhow, Error};
+use futures::stream::{self, BoxStream, Stream, StreamExt};
 use indicatif::{ProgressBar, ProgressStyle};
-use reqwest::{self, header::CONTENT_TYPE, Client};
+use itertools::Itertools;
+use reqwest::{
+    self,
+    header::{ACCEPT, CONTENT_TYPE},
+    Client,
+};
+#[cfg(test)]
+use rocket::http::{Accept, ContentType, Header};
 use rocket::serde::msgpack;
 use serde::{Deserialize, Serialize};
 use std::{
+    collections::HashMap,
     pin::Pin,
+    sync::{Arc, Mutex},
     task::{Context, Poll},
+    time::Duration,
 };
-use tokio::io::AsyncRead;
+use tokio::{io::AsyncRead, time};
 use tokio_util::io::ReaderStream;
 
-pub enum WebClient {
+/// Server key shares upload in chunks of this many bytes, see [`WebClient::submit_sks`]
+const SKS_CHUNK_SIZE: usize = 8 * 1024 * 1024;
+/// Times an interrupted upload resumes before giving up
+const SKS_UPLOAD_RETRIES: u32 = 5;
+/// Times following an FHE run reconnects in a row before giving up
+const FOLLOW_RETRIES: u32 = 5;
+
+/// The server turned a request down. A 4xx status is a mistake in the request, which fails
+/// again when retried, a 5xx status is the server's fault
+#[derive(Debug, thiserror::Error)]
+#[error("Server responded error {status}: {message:?}")]
+pub struct ResponseError {
+    pub status: u16,
+    pub message: String,
+}
+
+impl ResponseError {
+    fn new(status: u16, message: String) -> Self {
+        Self { status, message }
+    }
+
+    pub fn is_server_error(&self) -> bool {
+        self.status >= 500
+    }
+}
+
+#[derive(Clone)]
+enum Transport {
     Prod {
         url: String,
         client: reqwest::Client,
     },
-    Test(Box<rocket::local::asynchronous::Client>),
+    /// A local server, for the tests
+    #[cfg(test)]
+    Test(Arc<rocket::local::asynchronous::Client>),
+}
+
+/// Clones share the connection and the tokens, so each can join a different game
+#[derive(Clone)]
+pub struct WebClient {
+    transport: Transport,
+    /// The game the game routes act on, see [`WebClient::join_game`]
+    game_id: Option<GameId>,
+    /// Registration tokens, sent along with the requests made for their user
+    tokens: Arc<Mutex<HashMap<(GameId, UserId), String>>>,
 }
 
 impl WebClient {
     pub fn new(url: &str) -> Self {
-        Self::Prod {
-            url: url.to_string(),
-            client: Client::new(),
+        Self {
+            transport: Transport::Prod {
+                url: url.to_string(),
+                client: Client::new(),
+            },
+            game_id: None,
+            tokens: Arc::default(),
+        }
+    }
+
+    #[cfg(test)]
+    pub(crate) fn new_local(client: rocket::local::asynchronous::Client) -> Self {
+        Self {
+            transport: Transport::Test(Arc::new(client)),
+            game_id: None,
+            tokens: Arc::default(),
+        }
+    }
+
+    /// The local server of a test client
+    #[cfg(test)]
+    pub(crate) fn local(&self) -> &rocket::local::asynchronous::Client {
+        match &self.transport {
+            Transport::Prod { .. } => panic!("No local server in production"),
+            #[cfg(test)]
+            Transport::Test(client) => client,
         }
     }
 
     pub fn url(&self) -> String {
-        match self {
-            WebClient::Prod { url, .. } => url.to_string(),
-            WebClient::Test(_) => panic!("No url for testing"),
+        match &self.transport {
+            Transport::Prod { url, .. } => url.to_string(),
+            #[cfg(test)]
+            Transport::Test(_) => panic!("No url for testing"),
         }
     }
 
     fn path(&self, path: &str) -> String {
-        match self {
-            WebClient::Prod { url, .. } => format!("{}/{}", url, path),
-            WebClient::Test(_) => unreachable!(),
+        match &self.transport {
+            Transport::Prod { url, .. } => format!("{}/{}", url, path),
+            #[cfg(test)]
+            Transport::Test(_) => unreachable!(),
         }
     }
 
+    /// Route `path` to the joined game
+    fn game_path(&self, path: &str) -> Result<String, Error> {
+        let game_id = self.game_id.ok_or(anyhow!("Join a game first"))?;
+        Ok(format!("/games/{game_id}{path}"))
+    }
+
+    /// Act on `game_id` from now on
+    pub fn join_game(&mut self, game_id: GameId) {
+        self.game_id = Some(game_id);
+    }
+
+    pub fn game_id(&self) -> Option<GameId> {
+        self.game_id
+    }
+
+    /// The token `user_id` got when registering to the joined game
+    pub fn token(&self, user_id: UserId) -> Option<String> {
+        let game_id = self.game_id?;
+        self.tokens
+            .lock()
+            .unwrap()
+            .get(&(game_id, user_id))
+            .cloned()
+    }
+
+    /// Act as `user_id` of the joined game, e.g. after restarting the client
+    pub fn set_token(&self, user_id: UserId, token: &str) -> Result<(), Error> {
+        let game_id = self.game_id.ok_or(anyhow!("Join a game first"))?;
+        self.tokens
+            .lock()
+            .unwrap()
+            .insert((game_id, user_id), token.to_string());
+        Ok(())
+    }
+
+    fn bearer(&self, user_id: UserId) -> Result<String, Error> {
+        self.token(user_id)
+            .ok_or(anyhow!("No token for user #{user_id}, register first"))
+    }
+
     async fn get<T: Send + for<'de> Deserialize<'de> + 'static>(
         &self,
         path: &str,
     ) -> Result<T, Error> {
-        match self {
-            WebClient::Prod { client, .. } => {
+        match &self.transport {
+            Transport::Prod { client, .. } => {
                 let response = client.get(self.path(path)).send().await?;
                 handle_response_prod(response).await
             }
-            WebClient::Test(client) => {
+            #[cfg(test)]
+            Transport::Test(client) => {
                 let response = client.get(path).dispatch().await;
                 handle_response_test(response).await
             }
         }
     }
+    /// Like [`WebClient::get`], asking for msgpack. For ciphertexts, which are much larger
+    /// in JSON
+    async fn get_msgpack<T: Send + for<'de> Deserialize<'de> + 'static>(
+        &self,
+        path: &str,
+    ) -> Result<T, Error> {
+        match &self.transport {
+            Transport::Prod { client, .. } => {
+                let response = client
+                    .get(self.path(path))
+                    .header(ACCEPT, "application/msgpack")
+                    .send()
+                    .await?;
+                handle_response_prod(response).await
+            }
+            #[cfg(test)]
+            Transport::Test(client) => {
+                let response = client.get(path).header(Accept::MsgPack).dispatch().await;
+                handle_response_test(response).await
+            }
+        }
+    }
     async fn post_nobody<T: Send + for<'de> Deserialize<'de> + 'static>(
         &self,
         path: &str,
+        user_id: UserId,
     ) -> Result<T, Error> {
-        match self {
-            WebClient::Prod { client, .. } => {
-                let response = client.post(self.path(path)).send().await?;
+        let token = self.bearer(user_id)?;
+        match &self.transport {
+            Transport::Prod { client, .. } => {
+                let response = client
+                    .post(self.path(path))
+                    .bearer_auth(token)
+                    .send()
+                    .await?;
                 handle_response_prod(response).await
             }
-            WebClient::Test(client) => {
-                let response = client.post(path).dispatch().await;
+            #[cfg(test)]
+            Transport::Test(client) => {
+                let response = client
+                    .post(path)
+                    .header(bearer_header(&token))
+                    .dispatch()
+                    .await;
                 handle_response_test(response).await
             }
         }
@@ -83,61 +233,260 @@ impl WebClient {
         path: &str,
         body: Vec<u8>,
     ) -> Result<T, Error> {
-        match self {
-            WebClient::Prod { client, .. } => {
+        match &self.transport {
+            Transport::Prod { client, .. } => {
                 let response = client.post(self.path(path)).body(body).send().await?;
                 handle_response_prod(response).await
             }
-            WebClient::Test(client) => {
+            #[cfg(test)]
+            Transport::Test(client) => {
                 let response = client.post(path).body(body).dispatch().await;
                 handle_response_test(response).await
             }
         }
     }
+    async fn post_bytes<T: Send + for<'de> Deserialize<'de> + 'static>(
+        &self,
+        path: &str,
+        user_id: UserId,
+        body: Vec<u8>,
+    ) -> Result<T, Error> {
+        let token = self.bearer(user_id)?;
+        match &self.transport {
+            Transport::Prod { client, .. } => {
+                let response = client
+                    .post(self.path(path))
+                    .bearer_auth(token)
+                    .body(body)
+                    .send()
+                    .await?;
+                handle_response_prod(response).await
+            }
+            #[cfg(test)]
+            Transport::Test(client) => {
+                let response = client
+                    .post(path)
+                    .header(bearer_header(&token))
+                    .body(body)
+                    .dispatch()
+                    .await;
+                handle_response_test(response).await
+            }
+        }
+    }
     async fn post_msgpack<T: Send + for<'de> Deserialize<'de> + 'static>(
         &self,
         path: &str,
+        user_id: UserId,
         body: &impl Serialize,
     ) -> Result<T, Error> {
-        match self {
-            WebClient::Prod { client, .. } => {
+        let token = self.bearer(user_id)?;
+        match &self.transport {
+            Transport::Prod { client, .. } => {
                 let body = msgpack::to_compact_vec(body)?;
                 let reader = ProgressReader::new(&body, 128 * 1024);
                 let stream = ReaderStream::new(reader);
 
                 let response = client
                     .post(self.path(path))
+                    .bearer_auth(token)
                     .header(CONTENT_TYPE, "application/msgpack")
                     .body(reqwest::Body::wrap_stream(stream))
                     .send()
                     .await?;
                 handle_response_prod(response).await
             }
-            WebClient::Test(client) => {
-                let response = client.post(path).msgpack(body).dispatch().await;
+            #[cfg(test)]
+            Transport::Test(client) => {
+                let response = client
+                    .post(path)
+                    .header(bearer_header(&token))
+                    .msgpack(body)
+                    .dispatch()
+                    .await;
                 handle_response_test(response).await
             }
         }
     }
 
-    pub async fn get_seed(&self) -> Result<Seed, Error> {
-        self.get("/param").await
+    /// Create a game with the server defaults for what `config` leaves out
+    pub async fn create_game(&self, config: &GameConfig) -> Result<GameId, Error> {
+        self.post("/games", serde_json::to_vec(config)?).await
+    }
+
+    pub async fn get_param(&self) -> Result<Param, Error> {
+        self.get(&self.game_path("/param")?).await
     }
 
     pub async fn register(&self, name: &str) -> Result<RegisteredUser, Error> {
-        self.post("/register", name.as_bytes().to_vec()).await
+        let registration: Registration = self
+            .post(&self.game_path("/register")?, name.as_bytes().to_vec())
+            .await?;
+        self.set_token(registration.user.id, &registration.token)?;
+        Ok(registration.user)
+    }
+
+    pub async fn start_game(&self, user_id: UserId) -> Result<UserId, Error> {
+        self.post_nobody(&self.game_path(&format!("/start_game/{user_id}"))?, user_id)
+            .await
     }
 
     pub async fn get_dashboard(&self) -> Result<Dashboard, Error> {
-        self.get("/dashboard").await
+        self.get(&self.game_path("/dashboard")?).await
     }
 
-    pub async fn submit_sks(&self, user_id: UserId, sks: &ServerKeyShare) -> Result<UserId, Error> {
-        let submission = SksSubmission {
-            user_id,
-            sks: sks.clone(),
+    /// Block until the game reaches `state` in the current round, or past it.
+    /// Fails if that takes longer than `timeout`, or the FHE run of the round fails first
+    pub async fn wait_for_state(
+        &self,
+        state: ServerState,
+        timeout: Duration,
+    ) -> Result<Dashboard, Error> {
+        let dashboard = self
+            .wait(&format!("state={state:?}&timeout={}", timeout.as_secs()))
+            .await?;
+        match dashboard.get_fhe_failure() {
+            Some(reason) if dashboard.run_failed_before(&state, dashboard.get_round()) => {
+                Err(anyhow!("The FHE run failed: {reason}"))
+            }
+            _ => Ok(dashboard),
+        }
+    }
+
+    /// Block until the game reaches `round`, or fail after `timeout`
+    pub async fn wait_for_round(
+        &self,
+        round: usize,
+        timeout: Duration,
+    ) -> Result<Dashboard, Error> {
+        self.wait(&format!("round={round}&timeout={}", timeout.as_secs()))
+            .await
+    }
+
+    async fn wait(&self, query: &str) -> Result<Dashboard, Error> {
+        self.get(&self.game_path(&format!("/wait?{query}"))?).await
+    }
+
+    /// Dashboards pushed by the server whenever the game changes, starting from the current one
+    pub async fn dashboard_updates(
+        &self,
+    ) -> Result<BoxStream<'_, Result<Dashboard, Error>>, Error> {
+        let path = self.game_path("/events")?;
+        match &self.transport {
+            Transport::Prod { client, .. } => {
+                let response = client.get(self.path(&path)).send().await?;
+                if !response.status().is_success() {
+                    let status = response.status().as_u16();
+                    return Err(ResponseError::new(status, response.text().await?).into());
+                }
+                Ok(sse_json(response.bytes_stream()).boxed())
+            }
+            #[cfg(test)]
+            Transport::Test(client) => {
+                let response = client.get(path).dispatch().await;
+                if response.status().code != 200 {
+                    let status = response.status().code;
+                    let message = response.into_string().await.unwrap_or_default();
+                    return Err(ResponseError::new(status, message).into());
+                }
+                Ok(sse_json(ReaderStream::new(response)).boxed())
+            }
+        }
+    }
+
+    /// Follow the FHE run of the current round with a progress bar until it completes, however
+    /// long it takes. A dropped connection is followed again from the latest dashboard.
+    /// Fails if the run fails, or the server stays unreachable
+    pub async fn follow_fhe_run(&self) -> Result<Dashboard, Error> {
+        let bar = gates_bar();
+        let mut retries = 0;
+        let result = 'follow: loop {
+            let err = match self.dashboard_updates().await {
+                Ok(mut updates) => loop {
+                    let dashboard = match updates.next().await {
+                        Some(Ok(dashboard)) => dashboard,
+                        Some(Err(err)) => break err,
+                        None => break anyhow!("The server stopped sending updates"),
+                    };
+                    retries = 0;
+                    if let Some(reason) = dashboard.get_fhe_failure() {
+                        break 'follow Err(anyhow!("The FHE run failed: {reason}"));
+                    }
+                    if dashboard.is_fhe_complete() {
+                        break 'follow Ok(dashboard);
+                    }
+                    let progress = dashboard.get_progress();
+                    bar.set_length(progress.iter().map(|step| step.gates_total as u64).sum());
+                    bar.set_position(progress.iter().map(|step| step.gates_done as u64).sum());
+                    if let Some(step) = progress.iter().find(|step| !step.is_done()) {
+                        bar.set_message(step.step.clone());
+                    }
+                },
+                Err(err) => err,
+            };
+            if retries == FOLLOW_RETRIES {
+                break Err(err);
+            }
+            retries += 1;
+            bar.set_message(format!("Reconnecting after: {err}"));
+            time::sleep(Duration::from_secs(1 << retries)).await;
         };
-        self.post_msgpack("/submit_sks", &submission).await
+        match &result {
+            Ok(_) => bar.finish_with_message("FHE run complete"),
+            Err(_) => bar.abandon(),
+        }
+        result
+    }
+
+    /// Upload `sks` compressed, in chunks. After a network error or a server error the upload
+    /// resumes from the last chunk the server received
+    pub async fn submit_sks(&self, user_id: UserId, sks: &ServerKeyShare) -> Result<UserId, Error> {
+        let (start, body) = SksUploadStart::encode(sks, Compression::Zstd)?;
+        println!("Total size {} B", start.size);
+        let bar = progress_bar(start.size, "Uploading...");
+        let mut retries = 0;
+        loop {
+            match self.upload_sks(user_id, &start, &body, &bar).await {
+                Ok(user_id) => {
+                    bar.finish_with_message("Upload complete");
+                    return Ok(user_id);
+                }
+                Err(err) if retries < SKS_UPLOAD_RETRIES && is_transient(&err) => {
+                    retries += 1;
+                    bar.set_message(format!("Resuming after: {err}"));
+                    time::sleep(Duration::from_secs(1 << retries)).await;
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    async fn upload_sks(
+        &self,
+        user_id: UserId,
+        start: &SksUploadStart,
+        body: &[u8],
+        bar: &ProgressBar,
+    ) -> Result<UserId, Error> {
+        let path = self.game_path(&format!("/sks_upload/{user_id}"))?;
+        // The server tells where to carry on if it received some of these bytes before
+        let mut offset: u64 = self
+            .post_bytes(&path, user_id, serde_json::to_vec(start)?)
+            .await?;
+        while offset < start.size {
+            bar.set_position(offset);
+            let begin = offset as usize;
+            let end = body.len().min(begin + SKS_CHUNK_SIZE);
+            offset = self
+                .post_bytes(
+                    &format!("{path}/chunk/{offset}"),
+                    user_id,
+                    body[begin..end].to_vec(),
+                )
+                .await?;
+        }
+        bar.set_position(offset);
+        self.post_nobody(&format!("{path}/finish"), user_id).await
     }
 
     async fn setup_game(
@@ -145,8 +494,12 @@ impl WebClient {
         user_id: UserId,
         action: &UserAction<EncryptedWord>,
     ) -> Result<UserId, Error> {
-        self.post_msgpack(&format!("/setup_game/{user_id}"), action)
-            .await
+        self.post_msgpack(
+            &self.game_path(&format!("/setup_game/{user_id}"))?,
+            user_id,
+            action,
+        )
+        .await
     }
 
     async fn request_action(
@@ -154,8 +507,12 @@ impl WebClient {
         user_id: UserId,
         action: &UserAction<EncryptedWord>,
     ) -> Result<UserId, Error> {
-        self.post_msgpack(&format!("/request_action/{user_id}"), action)
-            .await
+        self.post_msgpack(
+            &self.game_path(&format!("/request_action/{user_id}"))?,
+            user_id,
+            action,
+        )
+        .await
     }
 
     pub async fn init_game(
@@ -173,8 +530,9 @@ impl WebClient {
         ck: &ClientKey,
         user_id: UserId,
         starting_coords: &(u8, u8),
+        board_dim: usize,
     ) -> Result<UserId, Error> {
-        let action = UserAction::set_starting_coord(ck, starting_coords);
+        let action = UserAction::set_starting_coord(ck, starting_coords, board_dim);
         self.setup_game(user_id, &action).await
     }
 
@@ -192,7 +550,7 @@ impl WebClient {
         &self,
         ck: &ClientKey,
         user_id: UserId,
-        user_int: u8,
+        user_int: u32,
     ) -> Result<UserId, Error> {
         let action = UserAction::add_int(ck, user_int);
         self.request_action(user_id, &action).await
@@ -213,7 +571,12 @@ impl WebClient {
     // `done` should be called after decrypted the output, and want to start a new
     pub async fn done(&self, user_id: UserId) -> Result<UserId, Error> {
         let action: &UserAction<EncryptedWord> = &UserAction::Done;
-        self.post_msgpack(&format!("/done/{user_id}"), action).await
+        self.post_msgpack(
+            &self.game_path(&format!("/done/{user_id}"))?,
+            user_id,
+            action,
+        )
+        .await
     }
 
     pub async fn get_cell(&self, user_id: usize) -> Result<UserId, Error> {
@@ -221,66 +584,172 @@ impl WebClient {
     }
 
     pub async fn trigger_fhe_run(&self, user_id: usize) -> Result<ServerState, Error> {
-        self.post_nobody(&format!("/run/{user_id}")).await
+        self.post_nobody(&self.game_path(&format!("/run/{user_id}"))?, user_id)
+            .await
     }
 
     pub async fn get_fhe_output(&self) -> Result<CircuitOutput, Error> {
-        self.get("/fhe_output").await
+        self.get_msgpack(&self.game_path("/fhe_output")?).await
     }
 
-    pub async fn submit_decryption_share(
+    /// Submit my shares of the outputs addressed to the other players, see
+    /// [`CircuitOutput::gen_decryption_shares`]
+    pub async fn submit_decryption_shares(
         &self,
         user_id: usize,
-        decryption_share: &AnnotatedDecryptionShare,
+        decryption_shares: &[AnnotatedDecryptionShare],
     ) -> Result<UserId, Error> {
         let submission = DecryptionShareSubmission {
             user_id,
-            decryption_share: decryption_share.clone(),
+            decryption_shares: decryption_shares.to_vec(),
         };
-        self.post_msgpack("/submit_decryption_share", &submission)
-            .await
+        self.post_msgpack(
+            &self.game_path("/submit_decryption_shares")?,
+            user_id,
+            &submission,
+        )
+        .await
     }
 
+    /// The share of `user_id` for the output `name`
     pub async fn get_decryption_share(
         &self,
-        output_id: usize,
-        user_id: usize,
+        name: &str,
+        user_id: UserId,
     ) -> Result<DecryptionShare, Error> {
-        self.get(&format!("/decryption_share/{output_id}/{user_id}"))
+        self.get_msgpack(&self.game_path(&format!("/decryption_share/{name}/{user_id}"))?)
             .await
     }
 }
 
+/// Decode the data of server-sent events as JSON
+fn sse_json<'a, T, B, E>(
+    bytes: impl Stream<Item = Result<B, E>> + Send + 'a,
+) -> impl Stream<Item = Result<T, Error>> + Send + 'a
+where
+    T: for<'de> Deserialize<'de> + Send + 'a,
+    B: AsRef<[u8]>,
+    E: Into<Error>,
+{
+    stream::unfold(
+        (Box::pin(bytes), vec![]),
+        |(mut bytes, mut buffer)| async move {
+            loop {
+                if let Some(end) = buffer.windows(2).position(|w| w == b"\n\n") {
+                    let event = buffer.drain(..end + 2).collect_vec();
+                    let event = String::from_utf8_lossy(&event);
+                    let data = event
+                        .lines()
+                        .filter_map(|line| line.strip_prefix("data:"))
+                        .map(|data| data.strip_prefix(' ').unwrap_or(data))
+                        .join("\n");
+                    // Comments, like keep-alive heartbeats, carry no data
+                    if data.is_empty() {
+                        continue;
+                    }
+                    let item = serde_json::from_str(&data).map_err(Error::from);
+                    return Some((item, (bytes, buffer)));
+                }
+                match bytes.next().await? {
+                    Ok(chunk) => buffer.extend_from_slice(chunk.as_ref()),
+                    Err(err) => return Some((Err(err.into()), (bytes, buffer))),
+                }
+            }
+        },
+    )
+}
+
+/// Network errors and server errors may pass, unlike a request the server turned down
+fn is_transient(err: &Error) -> bool {
+    err.is::<reqwest::Error>()
+        || err
+            .downcast_ref::<ResponseError>()
+            .is_some_and(ResponseError::is_server_error)
+}
+
+#[cfg(test)]
+fn bearer_header(token: &str) -> Header<'static> {
+    Header::new("Authorization", format!("Bearer {token}"))
+}
+
 async fn handle_response_prod<T: Send + for<'de> Deserialize<'de> + 'static>(
     response: reqwest::Response,
 ) -> Result<T, Error> {
+    let is_msgpack = response
+        .headers()
+        .get(CONTENT_TYPE)
+        .is_some_and(|value| value.as_bytes().starts_with(b"application/msgpack"));
     match response.status().as_u16() {
+        200 if is_msgpack => Ok(msgpack::from_slice(&download(response).await?)?),
         200 => Ok(response.json::<T>().await?),
-        _ => {
-            let err = response.text().await?;
-            bail!("Server responded error: {:?}", err)
-        }
+        status => Err(ResponseError::new(status, response.text().await?).into()),
     }
 }
 
+#[cfg(test)]
 async fn handle_response_test<T: Send + for<'de> Deserialize<'de> + 'static>(
     response: rocket::local::asynchronous::LocalResponse<'_>,
 ) -> Result<T, Error> {
+    let is_msgpack = response.content_type() == Some(ContentType::MsgPack);
     match response.status().code {
+        200 if is_msgpack => response
+            .into_msgpack::<T>()
+            .await
+            .ok_or(anyhow!("Can't parse response output")),
         200 => response
             .into_json::<T>()
             .await
             .ok_or(anyhow!("Can't parse response output")),
-        _ => {
-            let err = response
+        status => {
+            let message = response
                 .into_string()
                 .await
                 .ok_or(anyhow!("Can't parse response output"))?;
-            bail!("Server responded error: {:?}", err)
+            Err(ResponseError::new(status, message).into())
         }
     }
 }
 
+/// Read the body of `response`, with a progress bar like [`ProgressReader`] has for uploads
+async fn download(response: reqwest::Response) -> Result<Vec<u8>, Error> {
+    let total_bytes = response.content_length().unwrap_or(0);
+    println!("Total size {} B", total_bytes);
+    let bar = progress_bar(total_bytes, "Downloading...");
+    let mut body = Vec::with_capacity(total_bytes as usize);
+    let mut chunks = response.bytes_stream();
+    while let Some(chunk) = chunks.next().await {
+        body.extend_from_slice(&chunk?);
+        bar.set_position(body.len() as u64);
+    }
+    bar.finish_with_message("Download complete");
+    Ok(body)
+}
+
+fn progress_bar(total_bytes: u64, message: &'static str) -> ProgressBar {
+    let bar = ProgressBar::new(total_bytes);
+    bar.set_style(
+        ProgressStyle::with_template(
+            "[{elapsed_precise}] {bar:40.cyan/blue} {percent}% {bytes_per_sec} {msg}",
+        )
+        .unwrap()
+        .progress_chars("##-"),
+    );
+    bar.set_message(message);
+    bar
+}
+
+fn gates_bar() -> ProgressBar {
+    let bar = ProgressBar::new(0);
+    bar.set_style(
+        ProgressStyle::with_template(
+            "[{elapsed_precise}] {bar:40.cyan/blue} {percent}% {pos}/{len} gates {msg}",
+        )
+        .unwrap()
+        .progress_chars("##-"),
+    );
+    bar
+}
+
 struct ProgressReader {
     inner: Vec<u8>,
     progress_bar: ProgressBar,
@@ -292,15 +761,7 @@ impl ProgressReader {
     fn new(body: &[u8], chunk_size: usize) -> Self {
         let total_bytes = body.len() as u64;
         println!("Total size {} B", total_bytes);
-        let bar = ProgressBar::new(total_bytes);
-        bar.set_style(
-            ProgressStyle::with_template(
-                "[{elapsed_precise}] {bar:40.cyan/blue} {percent}% {bytes_per_sec} {msg}",
-            )
-            .unwrap()
-            .progress_chars("##-"),
-        );
-        bar.set_message("Uploading...");
+        let bar = progress_bar(total_bytes, "Uploading...");
 
         Self {
             inner: body.to_vec(),
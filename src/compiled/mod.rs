@@ -1,19 +1,122 @@
-#![allow(dead_code)]
-#![allow(clippy::all)]
-
-#[rustfmt::skip]
-mod add_to_int;
-#[rustfmt::skip]
-mod get_cell;
-#[rustfmt::skip]
-mod lay_egg;
-#[rustfmt::skip]
-mod move_player;
-#[rustfmt::skip]
-mod pickup_egg;
-
-pub(super) use add_to_int::add_to_int;
-pub(super) use get_cell::get_cell;
-pub(super) use lay_egg::lay_egg;
-pub(super) use move_player::move_player;
-pub(super) use pickup_egg::pickup_egg;
+//! The game circuits, evaluated on encrypted bits or on plain `bool`s through the same netlists
+
+mod netlist;
+
+pub use netlist::{
+    report_gates, CellType, Gate, GateInput, GateOps, GateReport, Level, Netlist, CIRCUITS_DIR_ENV,
+};
+
+use itertools::Itertools;
+use std::sync::OnceLock;
+
+/// Players compared by one evaluation of the `get_cell` netlist
+pub const GET_CELL_PLAYERS: usize = 4;
+
+/// Board dimensions with compiled circuits. A board of dimension `d` has `d * d` cells
+pub const BOARD_DIMS: [usize; 3] = [4, 8, 16];
+
+/// Parse a netlist once and keep it for the rest of the process.
+/// `$name.json` next to this file is embedded in the binary.
+macro_rules! netlist {
+    ($name:expr) => {{
+        static NETLIST: OnceLock<Netlist> = OnceLock::new();
+        NETLIST.get_or_init(|| Netlist::load($name, include_str!(concat!($name, ".json"))))
+    }};
+}
+
+/// The netlist of `$name` compiled for the board dimension `$dim`, from `${name}_${dim}.json`
+macro_rules! board_netlist {
+    ($name:literal, $dim:expr) => {
+        match $dim {
+            4 => netlist!(concat!($name, "_4")),
+            8 => netlist!(concat!($name, "_8")),
+            16 => netlist!(concat!($name, "_16")),
+            dim => panic!("No circuits for a {dim} x {dim} board"),
+        }
+    };
+}
+
+pub fn move_player<B: GateOps>(board_dim: usize, coords: &[B], direction: &[B]) -> Vec<B> {
+    move_player_netlist(board_dim).evaluate(&[coords, direction])
+}
+
+pub fn lay_egg<B: GateOps>(board_dim: usize, coords: &[B], eggs: &[B]) -> Vec<B> {
+    lay_egg_netlist(board_dim).evaluate(&[eggs, coords])
+}
+
+pub fn pickup_egg<B: GateOps>(board_dim: usize, coords: &[B], eggs: &[B]) -> Vec<B> {
+    pickup_egg_netlist(board_dim).evaluate(&[eggs, coords])
+}
+
+/// One bit per player in `players`, set if they stand on `coords`, then the egg bit of `coords`.
+/// Players are compared [`GET_CELL_PLAYERS`] at a time, padding the last group with `coords`
+pub fn get_cell<B: GateOps>(board_dim: usize, coords: &[B], eggs: &[B], players: &[B]) -> Vec<B> {
+    let width = coords.len();
+    assert!(
+        !players.is_empty() && players.len().is_multiple_of(width),
+        "Players must be whole coordinates"
+    );
+
+    let netlist = get_cell_netlist(board_dim);
+    let mut cell = Vec::with_capacity(players.len() / width + 1);
+    let mut egg = None;
+    for group in players.chunks(width * GET_CELL_PLAYERS) {
+        let padded = group
+            .iter()
+            .chain(coords.iter().cycle())
+            .take(width * GET_CELL_PLAYERS)
+            .cloned()
+            .collect_vec();
+        let out = netlist.evaluate(&[&padded, eggs, coords]);
+        cell.extend_from_slice(&out[..group.len() / width]);
+        egg = Some(out[GET_CELL_PLAYERS].clone());
+    }
+    cell.push(egg.expect("checked"));
+    cell
+}
+
+pub fn add_to_int<B: GateOps>(int_to_add: &[B], state: &[B]) -> Vec<B> {
+    add_to_int_netlist().evaluate(&[state, int_to_add])
+}
+
+/// Gates evaluated by [`move_player`] on a `board_dim` x `board_dim` board
+pub fn move_player_gates(board_dim: usize) -> usize {
+    move_player_netlist(board_dim).gate_count()
+}
+
+pub fn lay_egg_gates(board_dim: usize) -> usize {
+    lay_egg_netlist(board_dim).gate_count()
+}
+
+pub fn pickup_egg_gates(board_dim: usize) -> usize {
+    pickup_egg_netlist(board_dim).gate_count()
+}
+
+/// Gates evaluated by [`get_cell`] comparing `players` players
+pub fn get_cell_gates(board_dim: usize, players: usize) -> usize {
+    get_cell_netlist(board_dim).gate_count() * players.div_ceil(GET_CELL_PLAYERS)
+}
+
+pub fn add_to_int_gates() -> usize {
+    add_to_int_netlist().gate_count()
+}
+
+fn move_player_netlist(board_dim: usize) -> &'static Netlist {
+    board_netlist!("move_player", board_dim)
+}
+
+fn lay_egg_netlist(board_dim: usize) -> &'static Netlist {
+    board_netlist!("lay_egg", board_dim)
+}
+
+fn pickup_egg_netlist(board_dim: usize) -> &'static Netlist {
+    board_netlist!("pickup_egg", board_dim)
+}
+
+fn get_cell_netlist(board_dim: usize) -> &'static Netlist {
+    board_netlist!("get_cell", board_dim)
+}
+
+fn add_to_int_netlist() -> &'static Netlist {
+    netlist!("add_to_int")
+}
@@ -0,0 +1,504 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::backend::ParameterSet;
+use anyhow::{bail, Error};
+use phantom_zone::*;
+use rayon::prelude::*;
+use rocket::serde::{Deserialize, Serialize};
+
+/// Directory to load netlists from instead of the copies embedded in the binary
+pub const CIRCUITS_DIR_ENV: &str = "CHICKENS_CIRCUITS_DIR";
+
+/// Told the number of gates of every level once it is evaluated
+pub type GateReport = Arc<dyn Fn(usize) + Send + Sync>;
+
+thread_local! {
+    /// Where the netlists evaluated on this thread report their progress
+    static GATE_REPORT: RefCell<Option<GateReport>> = const { RefCell::new(None) };
+}
+
+/// Run `f`, reporting the gates of the netlists it evaluates on this thread to `report`
+pub fn report_gates<R>(report: GateReport, f: impl FnOnce() -> R) -> R {
+    let _outer = ReportGuard(GATE_REPORT.replace(Some(report)));
+    f()
+}
+
+/// Puts the outer report back when [`report_gates`] ends, even if it panicked
+struct ReportGuard(Option<GateReport>);
+
+impl Drop for ReportGuard {
+    fn drop(&mut self) {
+        GATE_REPORT.set(self.0.take());
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub enum GateInput {
+    Arg(usize, usize), // arg + index
+    Output(usize),     // reuse of output wire
+    Tv(usize),         // temp value
+    Cst(bool),         // constant
+}
+
+use GateInput::*;
+
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub enum CellType {
+    AND2,
+    NAND2,
+    XOR2,
+    XNOR2,
+    OR2,
+    NOR2,
+    INV,
+    /// Inputs are `[A, B, S]`, outputs `B` if `S` is set, otherwise `A`
+    MUX2,
+    BUF,
+}
+
+use CellType::*;
+
+impl CellType {
+    fn arity(&self) -> usize {
+        match self {
+            INV | BUF => 1,
+            AND2 | NAND2 | XOR2 | XNOR2 | OR2 | NOR2 => 2,
+            MUX2 => 3,
+        }
+    }
+
+    fn apply<B: GateOps>(&self, args: &[&B]) -> B {
+        match self {
+            AND2 => args[0].and2(args[1]),
+            NAND2 => args[0].nand2(args[1]),
+            OR2 => args[0].or2(args[1]),
+            NOR2 => args[0].nor2(args[1]),
+            XOR2 => args[0].xor2(args[1]),
+            XNOR2 => args[0].xnor2(args[1]),
+            INV => args[0].inv(),
+            MUX2 => args[2].mux2(args[0], args[1]),
+            BUF => args[0].clone(),
+        }
+    }
+
+    fn eval(&self, args: &[bool]) -> bool {
+        self.apply(&args.iter().collect::<Vec<_>>())
+    }
+
+    /// Simplify the gate given its (already substituted) inputs
+    fn fold(&self, inputs: Vec<GateInput>) -> Folded {
+        let constant = |input: &GateInput| match input {
+            Cst(value) => Some(*value),
+            _ => None,
+        };
+        if let Some(values) = inputs.iter().map(constant).collect::<Option<Vec<_>>>() {
+            return Folded::Wire(Cst(self.eval(&values)));
+        }
+        match self {
+            AND2 | NAND2 | OR2 | NOR2 | XOR2 | XNOR2 => {
+                let (value, wire) = match (constant(&inputs[0]), constant(&inputs[1])) {
+                    (Some(value), None) => (value, &inputs[1]),
+                    (None, Some(value)) => (value, &inputs[0]),
+                    _ => return Folded::Gate(*self, inputs),
+                };
+                with_free_input(self.eval(&[value, false]), self.eval(&[value, true]), wire)
+            }
+            MUX2 => match (
+                constant(&inputs[0]),
+                constant(&inputs[1]),
+                constant(&inputs[2]),
+            ) {
+                (_, _, Some(select)) => Folded::Wire(inputs[select as usize].clone()),
+                (Some(a), Some(b), None) => with_free_input(a, b, &inputs[2]),
+                (Some(false), None, None) => {
+                    Folded::Gate(AND2, vec![inputs[2].clone(), inputs[1].clone()])
+                }
+                (None, Some(true), None) => {
+                    Folded::Gate(OR2, vec![inputs[2].clone(), inputs[0].clone()])
+                }
+                _ => Folded::Gate(MUX2, inputs),
+            },
+            BUF => Folded::Wire(inputs[0].clone()),
+            INV => Folded::Gate(INV, inputs),
+        }
+    }
+}
+
+/// The output of a gate with a single non-constant input `wire`,
+/// given the outputs when `wire` is false and when it is true
+fn with_free_input(on_false: bool, on_true: bool, wire: &GateInput) -> Folded {
+    match (on_false, on_true) {
+        (false, true) => Folded::Wire(wire.clone()),
+        (true, false) => Folded::Gate(INV, vec![wire.clone()]),
+        (value, _) => Folded::Wire(Cst(value)),
+    }
+}
+
+enum Folded {
+    Gate(CellType, Vec<GateInput>),
+    /// The gate output is a constant or an existing wire
+    Wire(GateInput),
+}
+
+/// Boolean operations needed to evaluate a netlist
+pub trait GateOps: Clone + Send + Sync {
+    fn and2(&self, rhs: &Self) -> Self;
+    fn nand2(&self, rhs: &Self) -> Self;
+    fn or2(&self, rhs: &Self) -> Self;
+    fn nor2(&self, rhs: &Self) -> Self;
+    fn xor2(&self, rhs: &Self) -> Self;
+    fn xnor2(&self, rhs: &Self) -> Self;
+    fn inv(&self) -> Self;
+
+    /// Select `b` if `self` is set, otherwise `a`
+    fn mux2(&self, a: &Self, b: &Self) -> Self {
+        self.and2(b).or2(&self.inv().and2(a))
+    }
+
+    /// The bit of `value`, in the same domain as `like`
+    fn constant(value: bool, like: &Self) -> Self;
+
+    /// Run `f` where gates like `like` can be evaluated in parallel
+    fn install<R: Send>(_like: &Self, f: impl FnOnce() -> R + Send) -> R {
+        f()
+    }
+}
+
+/// Plaintext backend, handy to check circuits without spending minutes on FHE
+impl GateOps for bool {
+    fn and2(&self, rhs: &Self) -> Self {
+        *self & *rhs
+    }
+    fn nand2(&self, rhs: &Self) -> Self {
+        !(*self & *rhs)
+    }
+    fn or2(&self, rhs: &Self) -> Self {
+        *self | *rhs
+    }
+    fn nor2(&self, rhs: &Self) -> Self {
+        !(*self | *rhs)
+    }
+    fn xor2(&self, rhs: &Self) -> Self {
+        *self ^ *rhs
+    }
+    fn xnor2(&self, rhs: &Self) -> Self {
+        !(*self ^ *rhs)
+    }
+    fn inv(&self) -> Self {
+        !*self
+    }
+    fn mux2(&self, a: &Self, b: &Self) -> Self {
+        if *self {
+            *b
+        } else {
+            *a
+        }
+    }
+    fn constant(value: bool, _like: &Self) -> Self {
+        value
+    }
+}
+
+impl GateOps for FheBool {
+    fn and2(&self, rhs: &Self) -> Self {
+        self & rhs
+    }
+    fn nand2(&self, rhs: &Self) -> Self {
+        self.nand(rhs)
+    }
+    fn or2(&self, rhs: &Self) -> Self {
+        self | rhs
+    }
+    fn nor2(&self, rhs: &Self) -> Self {
+        self.nor(rhs)
+    }
+    fn xor2(&self, rhs: &Self) -> Self {
+        self ^ rhs
+    }
+    fn xnor2(&self, rhs: &Self) -> Self {
+        self.xnor(rhs)
+    }
+    fn inv(&self) -> Self {
+        !self
+    }
+    fn mux2(&self, a: &Self, b: &Self) -> Self {
+        // Every gate but NOT bootstraps, so the two ANDs run in parallel, then the OR
+        let (on_set, on_unset) = rayon::join(|| self & b, || &!self & a);
+        &on_set | &on_unset
+    }
+    /// Costs one bootstrap, as there is no trivial encryption to start from
+    fn constant(value: bool, like: &Self) -> Self {
+        // Any bit XORed with itself encrypts zero
+        #[allow(clippy::eq_op)]
+        let zero = like ^ like;
+        if value {
+            !&zero
+        } else {
+            zero
+        }
+    }
+    fn install<R: Send>(_like: &Self, f: impl FnOnce() -> R + Send) -> R {
+        ParameterSet::install(f)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct Gate {
+    pub id: usize,
+    pub is_output: bool,
+    pub cell: CellType,
+    pub inputs: Vec<GateInput>,
+}
+
+/// Gates in a level only depend on the previous levels, so they run in parallel
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct Level {
+    pub gates: Vec<Gate>,
+    /// Temp values no longer needed once this level is done
+    pub prune: Vec<usize>,
+}
+
+/// A levelized boolean circuit
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct Netlist {
+    /// Width of each argument word
+    pub args: Vec<usize>,
+    /// Width of the output word
+    pub outputs: usize,
+    pub levels: Vec<Level>,
+}
+
+impl Netlist {
+    pub fn from_json(json: &str) -> Result<Self, Error> {
+        let mut netlist: Self = serde_json::from_str(json)?;
+        netlist.validate()?;
+        netlist.fold_constants();
+        Ok(netlist)
+    }
+
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let json = std::fs::read_to_string(path)?;
+        Self::from_json(&json)
+    }
+
+    /// Load the netlist `name` from [`CIRCUITS_DIR_ENV`] when it is set, otherwise parse the embedded copy
+    pub fn load(name: &str, embedded: &str) -> Self {
+        let netlist = match std::env::var(CIRCUITS_DIR_ENV) {
+            Ok(dir) => Self::from_file(Path::new(&dir).join(format!("{name}.json"))),
+            Err(_) => Self::from_json(embedded),
+        };
+        netlist.unwrap_or_else(|err| panic!("Failed to load netlist {name}: {err}"))
+    }
+
+    fn validate(&self) -> Result<(), Error> {
+        let mut outputs = vec![false; self.outputs];
+        for (n, level) in self.levels.iter().enumerate() {
+            for gate in level.gates.iter() {
+                if gate.inputs.len() != gate.cell.arity() {
+                    bail!(
+                        "Level {n}: {:?} gate {} expects {} inputs but got {}",
+                        gate.cell,
+                        gate.id,
+                        gate.cell.arity(),
+                        gate.inputs.len()
+                    );
+                }
+                for input in gate.inputs.iter() {
+                    match input {
+                        Arg(pos, ndx) if !matches!(self.args.get(*pos), Some(w) if ndx < w) => {
+                            bail!("Level {n}: gate {} reads missing arg {pos}[{ndx}]", gate.id)
+                        }
+                        Output(ndx) if *ndx >= self.outputs => {
+                            bail!("Level {n}: gate {} reads missing output {ndx}", gate.id)
+                        }
+                        _ => {}
+                    }
+                }
+                if gate.is_output {
+                    match outputs.get_mut(gate.id) {
+                        Some(set) => *set = true,
+                        None => bail!("Level {n}: output {} out of range", gate.id),
+                    }
+                }
+            }
+        }
+        if let Some(ndx) = outputs.iter().position(|set| !set) {
+            bail!("Output {ndx} is never set");
+        }
+        Ok(())
+    }
+
+    /// Simplify gates with constant inputs at load time, so they don't cost bootstrapping.
+    /// Constant outputs and a few MUX2 cases still read constants when evaluated.
+    fn fold_constants(&mut self) {
+        let mut tv_wires: HashMap<usize, GateInput> = HashMap::new();
+        let mut out_wires: HashMap<usize, GateInput> = HashMap::new();
+        let mut changed = false;
+
+        for level in self.levels.iter_mut() {
+            let gates = std::mem::take(&mut level.gates);
+            for gate in gates {
+                let inputs = gate
+                    .inputs
+                    .iter()
+                    .map(|input| {
+                        let wire = match input {
+                            Tv(ndx) => tv_wires.get(ndx),
+                            Output(ndx) => out_wires.get(ndx),
+                            _ => None,
+                        };
+                        wire.unwrap_or(input).clone()
+                    })
+                    .collect::<Vec<_>>();
+                let wires = if gate.is_output {
+                    &mut out_wires
+                } else {
+                    &mut tv_wires
+                };
+
+                match gate.cell.fold(inputs) {
+                    Folded::Gate(cell, inputs) => {
+                        changed |= cell != gate.cell || inputs.len() != gate.inputs.len();
+                        wires.remove(&gate.id);
+                        level.gates.push(Gate {
+                            cell,
+                            inputs,
+                            ..gate
+                        });
+                    }
+                    Folded::Wire(wire) => {
+                        changed = true;
+                        wires.insert(gate.id, wire.clone());
+                        // Outputs still have to be set
+                        if gate.is_output {
+                            level.gates.push(Gate {
+                                cell: BUF,
+                                inputs: vec![wire],
+                                ..gate
+                            });
+                        }
+                    }
+                }
+            }
+        }
+
+        if changed {
+            // Substituted wires may be read later than they used to be
+            self.compute_prune();
+        }
+    }
+
+    /// Drop each temp value right after the last level reading it
+    fn compute_prune(&mut self) {
+        let mut last_use: HashMap<usize, usize> = HashMap::new();
+        for (n, level) in self.levels.iter().enumerate() {
+            for gate in level.gates.iter() {
+                if !gate.is_output {
+                    last_use.insert(gate.id, n);
+                }
+                for input in gate.inputs.iter() {
+                    if let Tv(ndx) = input {
+                        last_use.insert(*ndx, n);
+                    }
+                }
+            }
+        }
+        for level in self.levels.iter_mut() {
+            level.prune.clear();
+        }
+        for (ndx, n) in last_use {
+            self.levels[n].prune.push(ndx);
+        }
+        for level in self.levels.iter_mut() {
+            level.prune.sort();
+        }
+    }
+
+    fn uses_constants(&self) -> bool {
+        self.levels.iter().any(|level| {
+            level
+                .gates
+                .iter()
+                .any(|gate| gate.inputs.iter().any(|input| matches!(input, Cst(_))))
+        })
+    }
+
+    pub fn gate_count(&self) -> usize {
+        self.levels.iter().map(|level| level.gates.len()).sum()
+    }
+
+    pub fn evaluate<B: GateOps>(&self, args: &[&[B]]) -> Vec<B> {
+        assert_eq!(args.len(), self.args.len(), "Wrong number of arguments");
+        for (pos, (arg, width)) in args.iter().zip(self.args.iter()).enumerate() {
+            assert!(arg.len() >= *width, "Arg {pos} needs at least {width} bits");
+        }
+
+        let bit = args
+            .iter()
+            .find_map(|arg| arg.first())
+            .expect("Need at least one input bit");
+        // The levels run on a worker pool, out of reach of the thread local
+        let report = GATE_REPORT.with_borrow(|report| report.clone());
+        B::install(bit, || self.run(args, report.as_deref()))
+    }
+
+    fn run<B: GateOps>(
+        &self,
+        args: &[&[B]],
+        report: Option<&(dyn Fn(usize) + Send + Sync)>,
+    ) -> Vec<B> {
+        let mut temp_nodes: HashMap<usize, B> = HashMap::new();
+        let mut out: Vec<Option<B>> = vec![None; self.outputs];
+        let constants = self.uses_constants().then(|| {
+            let bit = args.iter().find_map(|arg| arg.first()).expect("checked");
+            [B::constant(false, bit), B::constant(true, bit)]
+        });
+
+        for level in self.levels.iter() {
+            let updates = level
+                .gates
+                .par_iter()
+                .map(|gate| {
+                    let inputs = gate
+                        .inputs
+                        .iter()
+                        .map(|input| match input {
+                            Cst(value) => &constants.as_ref().expect("derived")[*value as usize],
+                            Arg(pos, ndx) => &args[*pos][*ndx],
+                            Tv(ndx) => &temp_nodes[ndx],
+                            Output(ndx) => out[*ndx]
+                                .as_ref()
+                                .unwrap_or_else(|| panic!("Output node {ndx} not found")),
+                        })
+                        .collect::<Vec<_>>();
+                    ((gate.id, gate.is_output), gate.cell.apply(&inputs))
+                })
+                .collect::<Vec<_>>();
+
+            for ((index, is_output), value) in updates {
+                if is_output {
+                    out[index] = Some(value);
+                } else {
+                    temp_nodes.insert(index, value);
+                }
+            }
+            for ndx in level.prune.iter() {
+                temp_nodes.remove(ndx);
+            }
+            if let Some(report) = report {
+                report(level.gates.len());
+            }
+        }
+
+        out.into_iter().map(|c| c.unwrap()).collect()
+    }
+}
@@ -6,7 +6,7 @@ use tabled::{Table, Tabled};
 use crate::types::{ServerState, UserRecord};
 use crate::UserId;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(crate = "rocket::serde")]
 pub enum UserStatus {
     IDAcquired,
@@ -20,7 +20,7 @@ impl std::fmt::Display for UserStatus {
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, Tabled)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Tabled)]
 #[serde(crate = "rocket::serde")]
 pub struct RegisteredUser {
     pub id: UserId,
@@ -37,20 +37,25 @@ impl RegisteredUser {
         }
     }
 }
+/// The reply to a registration. Only the user gets the token, which authenticates their requests
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct Registration {
+    pub user: RegisteredUser,
+    pub token: String,
+}
+
 impl From<&UserRecord> for RegisteredUser {
     fn from(user: &UserRecord) -> Self {
         use crate::types::UserStorage::*;
         let status = match &user.storage {
             Empty => UserStatus::IDAcquired,
-            Sks(_) => UserStatus::SksSubmitted,
+            Sks => UserStatus::SksSubmitted,
             StartingCoords => UserStatus::StartingCoordsSubmitted,
-            DecryptionShare(share) => {
-                let result = match share {
-                    Some(_) => UserStatus::DecryptionShareSubmitted,
-                    None => UserStatus::StartingCoordsSubmitted,
-                };
-                result
-            }
+            DecryptionShares { submitted } => match submitted {
+                true => UserStatus::DecryptionShareSubmitted,
+                false => UserStatus::StartingCoordsSubmitted,
+            },
         };
 
         Self {
@@ -61,18 +66,59 @@ impl From<&UserRecord> for RegisteredUser {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// How far an FHE run got through one of its steps: a queued action, or the outputs
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct StepProgress {
+    pub step: String,
+    pub gates_done: usize,
+    pub gates_total: usize,
+}
+
+impl StepProgress {
+    pub(crate) fn new(step: String, gates_total: usize) -> Self {
+        Self {
+            step,
+            gates_done: 0,
+            gates_total,
+        }
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.gates_done >= self.gates_total
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Dashboard {
     status: ServerState,
     users: Vec<RegisteredUser>,
     round: usize,
+    min_players: usize,
+    max_players: usize,
+    /// Why the last FHE run failed, if it did. The round is open for actions again
+    fhe_failure: Option<String>,
+    /// The steps of the FHE run in progress
+    progress: Vec<StepProgress>,
 }
 impl Dashboard {
-    pub(crate) fn new(status: &ServerState, users: &[RegisteredUser], round: usize) -> Self {
+    pub(crate) fn new(
+        status: &ServerState,
+        users: &[RegisteredUser],
+        round: usize,
+        min_players: usize,
+        max_players: usize,
+        fhe_failure: Option<String>,
+        progress: Vec<StepProgress>,
+    ) -> Self {
         Self {
             status: status.clone(),
             users: users.to_vec(),
             round,
+            min_players,
+            max_players,
+            fhe_failure,
+            progress,
         }
     }
 
@@ -87,7 +133,19 @@ impl Dashboard {
         self.round
     }
 
+    pub fn get_fhe_failure(&self) -> Option<&str> {
+        self.fhe_failure.as_deref()
+    }
+
+    pub fn get_progress(&self) -> &[StepProgress] {
+        &self.progress
+    }
+
     /// APIs for client to check server state
+    pub fn can_start(&self) -> bool {
+        self.status == ServerState::ReadyForJoining && self.users.len() >= self.min_players
+    }
+
     pub fn is_concluded(&self) -> bool {
         self.status == ServerState::ReadyForServerKeyShares
     }
@@ -123,9 +181,40 @@ impl Dashboard {
         self.status == ServerState::ReadyForActions || self.round > round
     }
 
+    /// Whether the game got to `state` in `round`, or past it
+    pub fn has_reached(&self, state: &ServerState, round: usize) -> bool {
+        self.round > round || (self.round == round && self.status >= *state)
+    }
+
+    /// Whether the FHE run of `round` failed before the game got to `state`. The round is open
+    /// for actions again, so the game only gets there once a player acts again
+    pub fn run_failed_before(&self, state: &ServerState, round: usize) -> bool {
+        self.round == round
+            && self.fhe_failure.is_some()
+            && *state > ServerState::ReadyForActions
+            && !self.has_reached(state, round)
+    }
+
     pub fn print_presentation(&self) {
         println!("action no. {}", self.round);
         println!("🤖🧠 {}", self.status);
+        if let Some(reason) = &self.fhe_failure {
+            println!("💥 The last FHE run failed, act again: {reason}");
+        }
+        for step in self.progress.iter() {
+            println!(
+                "⚙️  {}: {}/{} gates",
+                step.step, step.gates_done, step.gates_total
+            );
+        }
+        if self.status == ServerState::ReadyForJoining {
+            println!(
+                "👥 {} joined, {} to {} players can play",
+                self.users.len(),
+                self.min_players,
+                self.max_players
+            );
+        }
         let users = Table::new(&self.users)
             .with(Style::ascii_rounded())
             .to_string();
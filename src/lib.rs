@@ -1,12 +1,14 @@
+mod backend;
 mod circuit;
 mod client;
-mod compiled;
+pub mod compiled;
 mod dashboard;
 mod server;
+mod storage;
 mod types;
 
-pub use client::WebClient;
-pub use server::{rocket, setup};
+pub use client::{ResponseError, WebClient};
+pub use server::{rocket, rocket_with, setup};
 pub use types::{
     CircuitOutput, ClientKey, DecryptionSharesMap, ServerState, UserAction, UserId, *,
 };
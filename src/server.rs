@@ -1,104 +1,495 @@
-use crate::circuit::{derive_server_key, evaluate_circuit, get_user_cell, PARAMETER};
-use crate::dashboard::{Dashboard, RegisteredUser};
+use crate::circuit::{action_gates, evaluate_circuit, get_outputs, output_gates};
+use crate::compiled::BOARD_DIMS;
+use crate::dashboard::{Dashboard, Registration, StepProgress};
+use crate::storage::Storage;
 
 use crate::types::{
-    CircuitOutput, DecryptionShare, DecryptionShareSubmission, EncryptedWord, Error, ErrorResponse,
-    GameStateEnc, MutexServerStorage, Seed, ServerState, ServerStorage, SksSubmission, UserId,
-    UserStorage,
+    Backend, CircuitOutput, DecryptionShare, DecryptionShareSubmission, EncryptedWord, Error,
+    ErrorResponse, FheProgress, GameConfig, GameId, GameStateEnc, Games, MutexServerStorage, Param,
+    ParameterSet, RoundMode, ServerState, ServerStorage, SksUpload, SksUploadStart, UserId,
+    UserStorage, DEFAULT_BOARD_DIM,
 };
 use crate::UserAction;
-use phantom_zone::{set_common_reference_seed, set_parameter_set};
+use itertools::Itertools;
 use rand::{thread_rng, RngCore};
+use rocket::data::ByteUnit;
+use rocket::fairing::AdHoc;
+use rocket::figment::Figment;
+use rocket::response::stream::{Event, EventStream};
 use rocket::serde::json::Json;
 use rocket::serde::msgpack::MsgPack;
-use rocket::{get, post, routes};
-use rocket::{Build, Rocket, State};
-use tokio::sync::Mutex;
-
-use rocket::http::Method;
+use rocket::serde::{Deserialize, Serialize};
+use rocket::tokio::{select, task::JoinError, time};
+use rocket::{catch, catchers, get, post, routes};
+use rocket::{Build, Rocket, Shutdown, State};
+
+use rocket::http::{ContentType, MediaType, Method, Status};
+use rocket::request::{self, FromRequest, Outcome, Request};
+use rocket::response::{self, Responder};
 use rocket_cors::{AllowedOrigins, CorsOptions};
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Seconds `wait` holds a request by default, and at most
+const DEFAULT_WAIT_TIMEOUT: u64 = 30;
+const MAX_WAIT_TIMEOUT: u64 = 300;
+
+/// How often the dashboard shows the progress of an FHE run
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The token a user got from `register`, sent as `Authorization: Bearer <token>`.
+/// Routes acting for a user check it with [`ServerStorage::authenticate`]
+struct Bearer(String);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Bearer {
+    type Error = Error;
+
+    async fn from_request(request: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        let token = request
+            .headers()
+            .get_one("Authorization")
+            .and_then(|value| value.strip_prefix("Bearer "));
+        match token {
+            Some(token) => Outcome::Success(Bearer(token.to_string())),
+            None => Outcome::Error((Status::Unauthorized, Error::MissingToken)),
+        }
+    }
+}
+
+/// Sent as msgpack to clients preferring `Accept: application/msgpack`, as JSON otherwise.
+/// Ciphertexts are several times smaller in msgpack
+struct Negotiated<T>(T);
+
+impl<'r, T: Serialize> Responder<'r, 'static> for Negotiated<T> {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'static> {
+        let msgpack = request
+            .accept()
+            .is_some_and(|accept| *accept.preferred().media_type() == MediaType::MsgPack);
+        if msgpack {
+            MsgPack(self.0).respond_to(request)
+        } else {
+            Json(self.0).respond_to(request)
+        }
+    }
+}
+
+/// A failed [`Bearer`] guard ends here, rather than on Rocket's HTML page
+#[catch(401)]
+fn missing_token() -> ErrorResponse {
+    Error::MissingToken.into()
+}
+
+/// Errors raised before a route runs, such as a malformed body, answer in JSON like the routes
+#[catch(default)]
+fn any_error(status: Status, _request: &Request) -> (Status, (ContentType, String)) {
+    let reason = status.reason_lossy().to_string();
+    (status, (ContentType::JSON, reason))
+}
+
+/// Game server settings, read from `Rocket.toml` or `ROCKET_*` environment variables.
+/// They are the defaults of every game created on the server
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+struct ServerConfig {
+    #[serde(default)]
+    backend: Backend,
+    /// Defaults to the smallest parameter set for `max_players`
+    parameter_set: Option<ParameterSet>,
+    /// Players needed before someone can start the game
+    #[serde(default = "default_min_players")]
+    min_players: usize,
+    /// Registration closes once this many players joined. Defaults to the parameter set limit
+    max_players: Option<usize>,
+    /// One of [`BOARD_DIMS`]
+    #[serde(default = "default_board_dim")]
+    board_dim: usize,
+    /// Games are saved here and reloaded on startup. Without it they only live in memory
+    data_dir: Option<PathBuf>,
+    /// Largest server key share a player may upload, compressed or not
+    #[serde(default = "default_max_sks_size")]
+    max_sks_size: ByteUnit,
+}
+
+fn default_min_players() -> usize {
+    2
+}
+
+fn default_board_dim() -> usize {
+    DEFAULT_BOARD_DIM
+}
+
+fn default_max_sks_size() -> ByteUnit {
+    ByteUnit::Megabyte(700)
+}
+
+impl ServerConfig {
+    /// A new game with a fresh seed. Settings missing from `config` are taken from the server
+    fn new_game(&self, config: GameConfig) -> Result<ServerStorage, Error> {
+        let backend = config.backend.unwrap_or(self.backend);
+        let max_players = config.max_players.or(self.max_players);
+        let parameter_set = match (config.parameter_set.or(self.parameter_set), max_players) {
+            (Some(parameter_set), _) => parameter_set,
+            (None, Some(max)) => ParameterSet::for_players(max).ok_or_else(|| {
+                Error::InvalidGameConfig(format!("No parameter set supports {max} players"))
+            })?,
+            (None, None) => ParameterSet::default(),
+        };
+        let limit = parameter_set.max_players();
+        let min = config.min_players.unwrap_or(self.min_players);
+        let max = max_players.unwrap_or(limit);
+        if !(2 <= min && min <= max && max <= limit) {
+            return Err(Error::InvalidGameConfig(format!(
+                "Players must be within 2..={limit} for {parameter_set}, got min {min} max {max}"
+            )));
+        }
+        let board_dim = config.board_dim.unwrap_or(self.board_dim);
+        if !BOARD_DIMS.contains(&board_dim) {
+            return Err(Error::InvalidGameConfig(format!(
+                "Board dimension must be one of {BOARD_DIMS:?}, got {board_dim}"
+            )));
+        }
 
-#[get("/param")]
-async fn get_param(ss: &State<MutexServerStorage>) -> Json<Seed> {
+        let seed = config.seed.unwrap_or_else(|| {
+            let mut seed = [0u8; 32];
+            thread_rng().fill_bytes(&mut seed);
+            seed
+        });
+        let param = Param {
+            backend,
+            parameter_set,
+            seed,
+            board_dim,
+            round_mode: config.round_mode.unwrap_or_default(),
+        };
+        Ok(ServerStorage::new(param, min, max))
+    }
+}
+
+/// Create a game. Settings left out of `config` fall back to the server config
+#[post("/games", data = "<config>")]
+async fn create_game(
+    config: Json<GameConfig>,
+    server_config: &State<ServerConfig>,
+    games: &State<Games>,
+) -> Result<Json<GameId>, ErrorResponse> {
+    let ss = server_config.new_game(config.0)?;
+    let (backend, parameter_set) = (ss.param.backend, ss.param.parameter_set);
+    let game_id = games.create(ss).await?;
+    println!("Created game #{game_id} on the {backend} backend with {parameter_set}");
+    Ok(Json(game_id))
+}
+
+#[get("/games/<game_id>/param")]
+async fn get_param(game_id: GameId, games: &State<Games>) -> Result<Json<Param>, ErrorResponse> {
+    let ss = games.get(game_id).await?;
     let ss = ss.lock().await;
-    Json(ss.seed)
+    Ok(Json(ss.param.clone()))
 }
 
 /// A user registers a name and get an ID
-/// We support 4 players
-#[post("/register", data = "<name>")]
+/// Registration closes by itself once the game is full
+#[post("/games/<game_id>/register", data = "<name>")]
 async fn register(
+    game_id: GameId,
     name: &str,
-    ss: &State<MutexServerStorage>,
-) -> Result<Json<RegisteredUser>, ErrorResponse> {
+    games: &State<Games>,
+) -> Result<Json<Registration>, ErrorResponse> {
+    let ss = games.get(game_id).await?;
     let mut ss = ss.lock().await;
     ss.ensure(ServerState::ReadyForJoining)?;
-    let user = ss.add_user(name);
+    let registration = ss.add_user(name);
     println!("{name} just joined!");
 
-    if ss.users.len() == 4 {
+    if ss.users.len() == ss.max_players {
         ss.transit(ServerState::ReadyForServerKeyShares);
-        println!("Got 4 players. Registration closed!");
+        println!("Got {} players. Registration closed!", ss.users.len());
     }
 
-    Ok(Json(user))
+    ss.changed();
+    Ok(Json(registration))
 }
 
-#[get("/dashboard")]
-async fn get_dashboard(ss: &State<MutexServerStorage>) -> Json<Dashboard> {
-    let dashboard = ss.lock().await.get_dashboard();
-    Json(dashboard)
+/// A registered user closes the registration before the game is full
+#[post("/games/<game_id>/start_game/<user_id>")]
+async fn start_game(
+    game_id: GameId,
+    user_id: UserId,
+    games: &State<Games>,
+    bearer: Bearer,
+) -> Result<Json<UserId>, ErrorResponse> {
+    let ss = games.get(game_id).await?;
+    let mut ss = ss.lock().await;
+    ss.authenticate(user_id, &bearer.0)?;
+    ss.ensure(ServerState::ReadyForJoining)?;
+    let name = ss.get_user(user_id)?.name.clone();
+
+    let players = ss.users.len();
+    if players < ss.min_players {
+        return Err(Error::NotEnoughPlayers {
+            min: ss.min_players,
+            got: players,
+        }
+        .into());
+    }
+    ss.transit(ServerState::ReadyForServerKeyShares);
+    println!("{name} started the game with {players} players. Registration closed!");
+
+    ss.changed();
+    Ok(Json(user_id))
 }
 
-/// The user submits server key shares
-#[post("/submit_sks", data = "<submission>", format = "msgpack")]
-async fn submit_sks(
-    submission: MsgPack<SksSubmission>,
-    ss: &State<MutexServerStorage>,
-) -> Result<Json<UserId>, ErrorResponse> {
+#[get("/games/<game_id>/dashboard")]
+async fn get_dashboard(
+    game_id: GameId,
+    games: &State<Games>,
+) -> Result<Json<Dashboard>, ErrorResponse> {
+    let dashboard = games.get(game_id).await?.lock().await.get_dashboard();
+    Ok(Json(dashboard))
+}
+
+/// Server-sent events with the dashboard, pushed whenever the game changes
+#[get("/games/<game_id>/events")]
+async fn dashboard_events(
+    game_id: GameId,
+    games: &State<Games>,
+    mut shutdown: Shutdown,
+) -> Result<EventStream![], ErrorResponse> {
+    let mut updates = games.get(game_id).await?.lock().await.subscribe();
+    Ok(EventStream! {
+        loop {
+            let dashboard = updates.borrow_and_update().clone();
+            yield Event::json(&dashboard);
+            select! {
+                changed = updates.changed() => if changed.is_err() { break },
+                _ = &mut shutdown => break,
+            }
+        }
+    })
+}
+
+/// Long-poll until the game reaches `state` in `round`, or past it. See [`Dashboard::has_reached`].
+/// Without `state` any state of the round will do, and without `round` it is the current round.
+/// Also returns once the FHE run fails before `state`, see [`Dashboard::run_failed_before`].
+/// Gives up after `timeout` seconds
+#[get("/games/<game_id>/wait?<state>&<round>&<timeout>")]
+async fn wait(
+    game_id: GameId,
+    state: Option<ServerState>,
+    round: Option<usize>,
+    timeout: Option<u64>,
+    games: &State<Games>,
+) -> Result<Json<Dashboard>, ErrorResponse> {
+    let mut updates = games.get(game_id).await?.lock().await.subscribe();
+    let state = state.unwrap_or_default();
+    let round = round.unwrap_or_else(|| updates.borrow().get_round());
+    let timeout = Duration::from_secs(
+        timeout
+            .unwrap_or(DEFAULT_WAIT_TIMEOUT)
+            .min(MAX_WAIT_TIMEOUT),
+    );
+
+    let reached = time::timeout(
+        timeout,
+        updates.wait_for(|d| d.has_reached(&state, round) || d.run_failed_before(&state, round)),
+    )
+    .await
+    .is_ok_and(|result| result.is_ok());
+    if !reached {
+        return Err(Error::WaitTimedOut { state, round }.into());
+    }
+    let dashboard = updates.borrow().clone();
+    Ok(Json(dashboard))
+}
+
+/// The user starts uploading their server key share in chunks. Starting again with the same
+/// share resumes the upload. Returns the bytes received so far
+#[post("/games/<game_id>/sks_upload/<user_id>", data = "<start>")]
+async fn start_sks_upload(
+    game_id: GameId,
+    user_id: UserId,
+    start: Json<SksUploadStart>,
+    server_config: &State<ServerConfig>,
+    games: &State<Games>,
+    bearer: Bearer,
+) -> Result<Json<u64>, ErrorResponse> {
+    let ss = games.get(game_id).await?;
     let mut ss = ss.lock().await;
+    ss.authenticate(user_id, &bearer.0)?;
+    ss.ensure(ServerState::ReadyForServerKeyShares)?;
+
+    let start = start.into_inner();
+    let max_size = server_config.max_sks_size.as_u64();
+    let upload = match ss.sks_uploads.remove(&user_id) {
+        Some(upload) if upload.start == start => upload,
+        _ => SksUpload::new(start, max_size)?,
+    };
+    let received = upload.received();
+    ss.sks_uploads.insert(user_id, upload);
+    Ok(Json(received))
+}
 
+/// The next chunk of the server key share, starting at `offset`. Returns the bytes received so
+/// far
+#[post(
+    "/games/<game_id>/sks_upload/<user_id>/chunk/<offset>",
+    data = "<chunk>"
+)]
+async fn upload_sks_chunk(
+    game_id: GameId,
+    user_id: UserId,
+    offset: u64,
+    chunk: Vec<u8>,
+    games: &State<Games>,
+    bearer: Bearer,
+) -> Result<Json<u64>, ErrorResponse> {
+    let ss = games.get(game_id).await?;
+    let mut ss = ss.lock().await;
+    ss.authenticate(user_id, &bearer.0)?;
     ss.ensure(ServerState::ReadyForServerKeyShares)?;
 
-    let SksSubmission { user_id, sks } = submission.0;
+    let upload = ss
+        .sks_uploads
+        .get_mut(&user_id)
+        .ok_or(Error::UploadNotStarted { user_id })?;
+    upload.append(offset, &chunk)?;
+    Ok(Json(upload.received()))
+}
 
+/// The user submits the server key share they uploaded, once it matches what they announced
+#[post("/games/<game_id>/sks_upload/<user_id>/finish")]
+async fn finish_sks_upload(
+    game_id: GameId,
+    user_id: UserId,
+    games: &State<Games>,
+    bearer: Bearer,
+) -> Result<Json<UserId>, ErrorResponse> {
+    let game = games.get(game_id).await?;
+    let mut ss = game.lock().await;
+    ss.authenticate(user_id, &bearer.0)?;
+    ss.ensure(ServerState::ReadyForServerKeyShares)?;
+    let upload = ss
+        .sks_uploads
+        .remove(&user_id)
+        .ok_or(Error::UploadNotStarted { user_id })?;
+    let backend = ss.param.backend;
+    let storage = ss.storage.clone();
+    drop(ss);
+
+    // Hashing, decoding and saving hundreds of MB, off the executor and the game lock
+    let (upload, sks) = tokio::task::spawn_blocking(move || {
+        let sks = upload
+            .finish()
+            .and_then(|sks| match sks.backend() {
+                got if got == backend => Ok(sks),
+                got => Err(Error::WrongBackend {
+                    expect: backend,
+                    got,
+                }),
+            })
+            .and_then(|sks| match &storage {
+                Some(storage) => storage
+                    .save_share(game_id, user_id, &sks)
+                    .map(|_| sks)
+                    .map_err(|err| Error::SaveFailed(err.to_string())),
+                None => Ok(sks),
+            });
+        (upload, sks)
+    })
+    .await
+    .map_err(|err| Error::InvalidUpload(panic_reason(err)))?;
+
+    let mut ss = game.lock().await;
+    let sks = match sks {
+        Ok(sks) => sks,
+        Err(err) => {
+            // The bytes stay until they make a share, so a failed finish can resume
+            ss.sks_uploads.entry(user_id).or_insert(upload);
+            return Err(err.into());
+        }
+    };
+    ss.ensure(ServerState::ReadyForServerKeyShares)?;
     let user = ss.get_user(user_id)?;
     println!("{} submited server key share.", user.name);
-    user.storage = UserStorage::Sks(Box::new(sks));
+    user.storage = UserStorage::Sks;
+    ss.server_key_shares.insert(user_id, sks);
 
+    let mut result = Ok(Json(user_id));
     if ss.check_cipher_submission() {
-        ss.transit(ServerState::ReadyForSetupGame);
-        let server_key_shares = ss.get_sks()?;
-        set_parameter_set(PARAMETER);
-        // Long running, global variable change
-        derive_server_key(&server_key_shares);
+        if let Some(derivation) = ss.start_key_derivation()? {
+            ss.changed();
+            drop(ss);
+            // Long running, unless the key is cached. The key stays with this game
+            let derived = tokio::task::spawn_blocking(move || derivation.run())
+                .await
+                .unwrap_or_else(|err| {
+                    let reason = panic_reason(err);
+                    Err((Error::ServerKeyFailed { reason }, vec![]))
+                });
+            ss = game.lock().await;
+            result = ss.end_key_derivation(derived).map(|_| Json(user_id));
+            if result.is_ok() {
+                ss.transit(ServerState::ReadyForSetupGame);
+            }
+        }
     }
 
-    Ok(Json(user_id))
+    // The share is in even if the key isn't
+    ss.changed();
+    Ok(result?)
 }
 
-#[post("/setup_game/<user_id>", data = "<action>", format = "msgpack")]
+#[post(
+    "/games/<game_id>/setup_game/<user_id>",
+    data = "<action>",
+    format = "msgpack"
+)]
 async fn setup_game(
+    game_id: GameId,
     user_id: UserId,
     action: MsgPack<UserAction<EncryptedWord>>,
-    ss: &State<MutexServerStorage>,
+    games: &State<Games>,
+    bearer: Bearer,
 ) -> Result<Json<UserId>, ErrorResponse> {
-    let mut ss = ss.lock().await;
+    let game = games.get(game_id).await?;
+    let mut ss = game.lock().await;
+    ss.authenticate(user_id, &bearer.0)?;
 
     ss.ensure(ServerState::ReadyForSetupGame)?;
+    // Unpacking switches keys under the parameters of the game
+    ss.init_thread();
 
     let user = ss.get_user(user_id)?;
-    println!("{} requested action {}", user.name, action.to_string());
+    println!("{} requested action {}", user.name, *action);
     let action = action.unpack(user_id);
+    action.check_widths()?;
 
     let result = match action {
-        UserAction::InitGame { initial_eggs } => {
+        UserAction::InitGame {
+            initial_eggs,
+            initial_int,
+        } => {
+            let board_dim = ss.param.board_dim;
+            if initial_eggs.len() != board_dim * board_dim {
+                return Err(Error::WrongBoardSize {
+                    expect: board_dim * board_dim,
+                    got: initial_eggs.len(),
+                }
+                .into());
+            }
             match &mut ss.game_state {
-                Some(game_state) => game_state.eggs = initial_eggs,
+                Some(game_state) => {
+                    game_state.eggs = initial_eggs;
+                    game_state.int = initial_int;
+                }
                 None => {
                     ss.game_state = Some(GameStateEnc {
-                        coords: vec![None; 4],
+                        board_dim,
+                        coords: vec![None; ss.users.len()],
                         eggs: initial_eggs,
+                        int: initial_int,
                     })
                 }
             };
@@ -109,18 +500,22 @@ async fn setup_game(
             match &mut ss.game_state {
                 Some(game_state) => game_state.coords[user_id] = Some(starting_coord),
                 None => {
-                    let mut coords = vec![None; 4];
+                    let mut coords = vec![None; ss.users.len()];
                     coords[user_id] = Some(starting_coord);
                     ss.game_state = Some(GameStateEnc {
+                        board_dim: ss.param.board_dim,
                         coords,
                         eggs: vec![],
+                        int: vec![],
                     });
                 }
             };
             if ss.check_setup_game_complete() {
-                ss.transit(ServerState::ReadyForActions);
+                if let Some(deadline) = ss.open_round() {
+                    close_actions_after(game.clone(), ss.round, deadline);
+                }
                 for user in ss.users.iter_mut() {
-                    user.storage = UserStorage::DecryptionShare(None);
+                    user.storage = UserStorage::DecryptionShares { submitted: false };
                 }
             }
             Ok(Json(user_id))
@@ -132,33 +527,58 @@ async fn setup_game(
         .into()),
     };
 
+    ss.changed();
     result
 }
 
-#[post("/request_action/<user_id>", data = "<action>", format = "msgpack")]
+#[post(
+    "/games/<game_id>/request_action/<user_id>",
+    data = "<action>",
+    format = "msgpack"
+)]
 async fn request_action(
+    game_id: GameId,
     user_id: UserId,
     action: MsgPack<UserAction<EncryptedWord>>,
-    ss: &State<MutexServerStorage>,
+    games: &State<Games>,
+    bearer: Bearer,
 ) -> Result<Json<UserId>, ErrorResponse> {
-    let mut ss = ss.lock().await;
+    let game = games.get(game_id).await?;
+    let mut ss = game.lock().await;
+    ss.authenticate(user_id, &bearer.0)?;
 
     ss.ensure(ServerState::ReadyForActions)?;
+    if ss.action_queue.iter().any(|(actor, _)| *actor == user_id) {
+        return Err(Error::AlreadyActed { user_id }.into());
+    }
+    ss.init_thread();
 
     let user = ss.get_user(user_id)?;
-    println!("{} requested action {}", user.name, action.to_string());
+    println!("{} requested action {}", user.name, *action);
     let action = action.unpack(user_id);
+    action.check_widths()?;
 
     let result = match action {
         UserAction::MovePlayer { .. }
         | UserAction::AddInt { .. }
-        | UserAction::ViewInt { .. }
-        | UserAction::LayEgg { .. }
-        | UserAction::PickupEgg { .. }
-        | UserAction::GetCell { .. } => {
+        | UserAction::ViewInt
+        | UserAction::LayEgg
+        | UserAction::PickupEgg
+        | UserAction::GetCell => {
             ss.action_queue.push((user_id, action));
-            ss.transit(ServerState::ReadyForRunning);
-            Ok(Json(user_id))
+            // Acting again moves on from a failed run
+            ss.fhe_failure = None;
+            let mut result = Ok(Json(user_id));
+            if ss.check_actions_complete() {
+                ss.transit(ServerState::ReadyForRunning);
+                // Nobody in particular triggers the run of a simultaneous round
+                if let RoundMode::Simultaneous { .. } = ss.param.round_mode {
+                    result = start_fhe_run(game.clone(), &mut ss)
+                        .map(|_| Json(user_id))
+                        .map_err(Into::into);
+                }
+            }
+            result
         }
         _ => Err(Error::WrongServerState {
             expect: ServerState::ReadyForActions.to_string(),
@@ -167,21 +587,31 @@ async fn request_action(
         .into()),
     };
 
+    ss.changed();
     result
 }
 
-#[post("/done/<user_id>", data = "<action>", format = "msgpack")]
+#[post(
+    "/games/<game_id>/done/<user_id>",
+    data = "<action>",
+    format = "msgpack"
+)]
 async fn done(
+    game_id: GameId,
     user_id: UserId,
     action: MsgPack<UserAction<EncryptedWord>>,
-    ss: &State<MutexServerStorage>,
+    games: &State<Games>,
+    bearer: Bearer,
 ) -> Result<Json<UserId>, ErrorResponse> {
-    let mut ss = ss.lock().await;
+    let game = games.get(game_id).await?;
+    let mut ss = game.lock().await;
+    ss.authenticate(user_id, &bearer.0)?;
 
     ss.ensure(ServerState::CompletedFhe)?;
+    ss.init_thread();
 
     let user = ss.get_user(user_id)?;
-    println!("{} requested action {}", user.name, action.to_string());
+    println!("{} requested action {}", user.name, *action);
     let action = action.unpack(user_id);
 
     let result = match action {
@@ -198,60 +628,55 @@ async fn done(
 
     if ss.check_ready_for_new_round() {
         ss.round += 1;
-        ss.transit(ServerState::ReadyForActions);
+        if let Some(deadline) = ss.open_round() {
+            close_actions_after(game.clone(), ss.round, deadline);
+        }
         for user in ss.users.iter_mut() {
             user.ready_for_new_round = false;
-            user.storage = UserStorage::DecryptionShare(None);
+            user.storage = UserStorage::DecryptionShares { submitted: false };
         }
+        ss.decryption_shares.clear();
     }
 
+    ss.changed();
     result
 }
 
-#[post("/run/<user_id>")]
+#[post("/games/<game_id>/run/<user_id>")]
 async fn run(
+    game_id: GameId,
     user_id: UserId,
-    ss: &State<MutexServerStorage>,
+    games: &State<Games>,
+    bearer: Bearer,
 ) -> Result<Json<ServerState>, ErrorResponse> {
-    let s2 = (*ss).clone();
-    let mut ss = ss.lock().await;
+    let game = games.get(game_id).await?;
+    let mut ss = game.lock().await;
+    ss.authenticate(user_id, &bearer.0)?;
 
+    if ss.state == ServerState::ReadyForActions && ss.check_actions_complete() {
+        // The deadline passed while the server was down
+        ss.transit(ServerState::ReadyForRunning);
+    }
     match &ss.state {
         ServerState::ReadyForRunning => {
-            let game_state = ss.game_state.clone().ok_or(Error::GameNotInitedYet)?;
-            let uas = ss.action_queue.clone();
-
-            tokio::task::spawn_blocking(move || {
-                rayon::ThreadPoolBuilder::new()
-                    .build_scoped(
-                        // Initialize thread-local storage parameters
-                        |thread| {
-                            set_parameter_set(PARAMETER);
-                            thread.run()
-                        },
-                        // Run parallel code under this pool
-                        |pool| {
-                            pool.install(|| {
-                                println!("Begin FHE run");
-                                // Long running
-                                let final_game_state = evaluate_circuit(game_state, &uas);
-
-                                let cell = get_user_cell(&final_game_state, user_id);
-                                let mut ss = s2.blocking_lock();
-                                ss.game_state = Some(final_game_state);
-                                let cell = CircuitOutput::new(cell);
-                                ss.circuit_output = Some(cell);
-
-                                ss.transit(ServerState::CompletedFhe);
-                                println!("FHE computation completed");
-                            })
-                        },
-                    )
-                    .unwrap();
-            });
-            ss.transit(ServerState::RunningFhe);
+            if let Err(err) = start_fhe_run(game.clone(), &mut ss) {
+                // Save the round closed above
+                ss.changed();
+                return Err(err.into());
+            }
             Ok(Json(ServerState::RunningFhe))
         }
+        // The players act again after a failed run
+        ServerState::ReadyForActions if ss.fhe_failure.is_some() => Err(Error::FheRunFailed {
+            reason: ss.fhe_failure.clone().unwrap_or_default(),
+        }
+        .into()),
+        // The other players are still acting
+        ServerState::ReadyForActions
+            if matches!(ss.param.round_mode, RoundMode::Simultaneous { .. }) =>
+        {
+            Ok(Json(ServerState::ReadyForActions))
+        }
         ServerState::RunningFhe => Ok(Json(ServerState::RunningFhe)),
         ServerState::CompletedFhe => Ok(Json(ServerState::CompletedFhe)),
         _ => Err(Error::WrongServerState {
@@ -262,63 +687,232 @@ async fn run(
     }
 }
 
-#[get("/fhe_output")]
+/// Evaluate the queued actions on a blocking thread, and output the words of every player once
+/// done. If the run fails, its actions are dropped and the round opens again
+fn start_fhe_run(game: MutexServerStorage, ss: &mut ServerStorage) -> Result<(), Error> {
+    let game_state = ss.game_state.clone().ok_or(Error::GameNotInitedYet)?;
+    // The queue is kept until the run completes, so a restart can run it again
+    let uas = ss.action_queue.clone();
+    let param = ss.param.clone();
+    let server_key = ss.server_key.clone().ok_or(Error::GameNotInitedYet)?;
+    let total_users = ss.users.len();
+    let board_dim = game_state.board_dim;
+    let mut steps = uas
+        .iter()
+        .map(|(user_id, ua)| {
+            StepProgress::new(
+                format!("{ua} of user #{user_id}"),
+                action_gates(board_dim, ua),
+            )
+        })
+        .collect_vec();
+    steps.push(StepProgress::new(
+        "Outputs".to_string(),
+        output_gates(board_dim, total_users),
+    ));
+    let progress = FheProgress::new(steps);
+    ss.fhe_progress = Some(progress.clone());
+
+    let job = tokio::task::spawn_blocking(move || {
+        param.init_thread();
+        println!("Begin FHE run");
+        // Long running. The circuits run on a pool set up with the key of this game
+        server_key.scope(|| {
+            progress.scope(|| {
+                let final_game_state = evaluate_circuit(game_state, &uas);
+                let outputs = (0..total_users)
+                    .flat_map(|user_id| get_outputs(&final_game_state, &uas, user_id))
+                    .collect_vec();
+                (final_game_state, outputs)
+            })
+        })
+    });
+    publish_progress(game.clone(), ss.round);
+    tokio::spawn(async move {
+        let result = job.await;
+        let mut ss = game.lock().await;
+        ss.fhe_progress = None;
+        match result {
+            Ok((final_game_state, outputs)) => {
+                ss.game_state = Some(final_game_state);
+                // Actions are consumed by this run, so they won't be applied again next round
+                ss.action_queue.clear();
+                ss.circuit_output = Some(CircuitOutput::new(outputs));
+                ss.transit(ServerState::CompletedFhe);
+                println!("FHE computation completed");
+            }
+            Err(err) => {
+                let reason = panic_reason(err);
+                println!("FHE run of game #{} failed: {reason}", ss.id);
+                // The game state is untouched, the players act again
+                ss.action_queue.clear();
+                ss.fhe_failure = Some(reason);
+                if let Some(deadline) = ss.open_round() {
+                    close_actions_after(game.clone(), ss.round, deadline);
+                }
+            }
+        }
+        ss.changed();
+    });
+    ss.fhe_failure = None;
+    ss.transit(ServerState::RunningFhe);
+    ss.changed();
+    Ok(())
+}
+
+/// Show the progress of the FHE run of `round` on the dashboard until the run is over
+fn publish_progress(game: MutexServerStorage, round: usize) {
+    tokio::spawn(async move {
+        loop {
+            time::sleep(PROGRESS_INTERVAL).await;
+            let ss = game.lock().await;
+            if ss.round != round || ss.state != ServerState::RunningFhe {
+                return;
+            }
+            ss.publish();
+        }
+    });
+}
+
+/// What a failed job panicked with
+fn panic_reason(err: JoinError) -> String {
+    match err.try_into_panic() {
+        Ok(panic) => panic
+            .downcast_ref::<&str>()
+            .map(|reason| reason.to_string())
+            .or_else(|| panic.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "Unknown panic".to_string()),
+        Err(err) => err.to_string(),
+    }
+}
+
+/// Close the actions of a simultaneous round once its deadline passes, and run the ones in
+fn close_actions_after(game: MutexServerStorage, round: usize, deadline: Duration) {
+    tokio::spawn(async move {
+        time::sleep(deadline).await;
+        let mut ss = game.lock().await;
+        if ss.round != round
+            || ss.state != ServerState::ReadyForActions
+            || !ss.check_actions_complete()
+        {
+            // Everyone acted in time, or nobody did and the first action closes the round
+            return;
+        }
+        println!("Round {round} of game #{} is past its deadline", ss.id);
+        ss.transit(ServerState::ReadyForRunning);
+        if let Err(err) = start_fhe_run(game.clone(), &mut ss) {
+            println!("Failed to run round {round} of game #{}: {err}", ss.id);
+            ss.changed();
+        }
+    });
+}
+
+#[get("/games/<game_id>/fhe_output")]
 async fn get_fhe_output(
-    ss: &State<MutexServerStorage>,
-) -> Result<Json<CircuitOutput>, ErrorResponse> {
+    game_id: GameId,
+    games: &State<Games>,
+) -> Result<Negotiated<CircuitOutput>, ErrorResponse> {
+    let ss = games.get(game_id).await?;
     let ss = ss.lock().await;
     ss.ensure(ServerState::CompletedFhe)?;
     let cell = ss.circuit_output.clone().ok_or(Error::CellNotFound)?;
-    Ok(Json(cell))
+    Ok(Negotiated(cell))
 }
 
-/// The user submits the ciphertext
-#[post("/submit_decryption_share", data = "<submission>", format = "msgpack")]
-async fn submit_decryption_share(
+/// The user submits their shares of the outputs of the other players
+#[post(
+    "/games/<game_id>/submit_decryption_shares",
+    data = "<submission>",
+    format = "msgpack"
+)]
+async fn submit_decryption_shares(
+    game_id: GameId,
     submission: MsgPack<DecryptionShareSubmission>,
-    ss: &State<MutexServerStorage>,
+    games: &State<Games>,
+    bearer: Bearer,
 ) -> Result<Json<UserId>, ErrorResponse> {
     let user_id = submission.user_id;
+    let ss = games.get(game_id).await?;
     let mut ss = ss.lock().await;
+    ss.authenticate(user_id, &bearer.0)?;
     ss.ensure(ServerState::CompletedFhe)?;
-    let decryption_share = ss
-        .get_user(user_id)?
-        .storage
-        .get_mut_decryption_share()
-        .ok_or(Error::OutputNotReady)?;
-    let output_id = submission.decryption_share.0;
-    let share = &submission.decryption_share.1;
-    *decryption_share = Some(share.clone());
-
-    ss.decryption_shares
-        .insert((output_id, user_id), share.clone());
+    let output = ss.circuit_output.as_ref().ok_or(Error::OutputNotReady)?;
+    for (name, _) in submission.decryption_shares.iter() {
+        output.check_shareable(name, user_id)?;
+    }
+    let names = submission
+        .decryption_shares
+        .iter()
+        .map(|(name, _)| name)
+        .collect::<HashSet<_>>();
+    if output
+        .outputs()
+        .any(|output| output.recipient != user_id && !names.contains(&output.name))
+    {
+        return Err(Error::MissingDecryptionShares { user_id }.into());
+    }
+    ss.get_user(user_id)?.storage = UserStorage::DecryptionShares { submitted: true };
+
+    let submission = submission.into_inner();
+    for (name, share) in submission.decryption_shares {
+        ss.decryption_shares.insert((name, user_id), share);
+    }
+    ss.changed();
     Ok(Json(user_id))
 }
 
-#[get("/decryption_share/<output_id>/<user_id>")]
+/// The share of `user_id` for the output `name`
+#[get("/games/<game_id>/decryption_share/<name>/<user_id>")]
 async fn get_decryption_share(
-    output_id: usize,
+    game_id: GameId,
+    name: &str,
     user_id: UserId,
-    ss: &State<MutexServerStorage>,
-) -> Result<Json<DecryptionShare>, ErrorResponse> {
-    let mut ss: tokio::sync::MutexGuard<ServerStorage> = ss.lock().await;
+    games: &State<Games>,
+) -> Result<Negotiated<DecryptionShare>, ErrorResponse> {
+    let ss = games.get(game_id).await?;
+    let ss = ss.lock().await;
     ss.ensure(ServerState::CompletedFhe)?;
+    let output = ss.circuit_output.as_ref().ok_or(Error::OutputNotReady)?;
+    output.check_shareable(name, user_id)?;
     let decryption_share = ss
         .decryption_shares
-        .get(&(output_id, user_id))
-        .ok_or(Error::OutputNotReady)?;
-    Ok(Json(decryption_share.clone()))
+        .get(&(name.to_string(), user_id))
+        .ok_or(Error::DecryptionShareNotFound { user_id })?;
+    Ok(Negotiated(decryption_share.clone()))
 }
 
-pub fn setup(seed: &Seed) {
-    set_parameter_set(PARAMETER);
-    set_common_reference_seed(*seed);
+/// Set up the current thread for the backend, parameter set and seed of a game.
+/// For PhantomZone the seed goes to a process-wide common reference string, so a client
+/// process can only set up one game. The server never generates keys and doesn't need it
+pub fn setup(param: &Param) {
+    param.backend.setup(param.parameter_set, &param.seed);
 }
 
 pub fn rocket() -> Rocket<Build> {
-    let mut seed = [0u8; 32];
-    thread_rng().fill_bytes(&mut seed);
-    setup(&seed);
+    rocket_with(rocket::Config::figment())
+}
+
+/// Build the server from a custom configuration, e.g. to pick the backend in tests
+pub fn rocket_with(figment: Figment) -> Rocket<Build> {
+    let config: ServerConfig = figment.extract().expect("Valid server config");
+    // Catch a bad config at launch rather than on the first game
+    if let Err(err) = config.new_game(GameConfig::default()) {
+        panic!("{err}");
+    }
+    println!(
+        "New games default to the {} backend on a {}x{} board",
+        config.backend, config.board_dim, config.board_dim
+    );
+    let games = match &config.data_dir {
+        Some(data_dir) => {
+            let storage = Storage::new(data_dir);
+            let saved = storage.load();
+            let resumed = saved.iter().flatten().count();
+            println!("Resumed {resumed} games from {}", data_dir.display());
+            Games::restore(Arc::new(storage), saved)
+        }
+        None => Games::default(),
+    };
 
     let cors = CorsOptions::default()
         .allowed_origins(AllowedOrigins::all())
@@ -330,24 +924,37 @@ pub fn rocket() -> Rocket<Build> {
         )
         .allow_credentials(true);
 
-    rocket::build()
+    rocket::custom(figment)
         .attach(cors.to_cors().unwrap())
-        .manage(MutexServerStorage::new(Mutex::new(ServerStorage::new(
-            seed,
-        ))))
+        .attach(AdHoc::on_shutdown("Save the games", |rocket| {
+            Box::pin(async move {
+                if let Some(games) = rocket.state::<Games>() {
+                    games.flush().await;
+                }
+            })
+        }))
+        .manage(config)
+        .manage(games)
+        .register("/", catchers![missing_token, any_error])
         .mount(
             "/",
             routes![
+                create_game,
                 get_param,
                 register,
+                start_game,
                 get_dashboard,
-                submit_sks,
+                dashboard_events,
+                wait,
+                start_sks_upload,
+                upload_sks_chunk,
+                finish_sks_upload,
                 setup_game,
                 request_action,
                 done,
                 run,
                 get_fhe_output,
-                submit_decryption_share,
+                submit_decryption_shares,
                 get_decryption_share,
             ],
         )
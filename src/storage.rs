@@ -0,0 +1,279 @@
+use crate::backend::ServerKey;
+use crate::types::{GameId, Seed, ServerKeyShare, ServerState, ServerStorage, UserId, UserStorage};
+use anyhow::{anyhow, Error};
+use ring::digest::{digest, Context, SHA256};
+use rocket::serde::{de::DeserializeOwned, Serialize};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Mutex;
+use std::thread;
+
+/// Snapshots of the games in a data directory, so they survive a restart.
+/// Each game gets a directory named by its ID. Server key shares are large and never change,
+/// so they are written once in their own files. The rest of the game is written again after
+/// every change.
+/// Server keys are cached in `server_keys/`, named by [`server_key_hash`], so a game with the
+/// same seed and shares loads its key instead of aggregating it again.
+/// Games are written by a thread of their own, in the order they changed, so requests don't
+/// wait on the disk. Shares and keys are written by the blocking jobs that make them
+#[derive(Debug)]
+pub(crate) struct Storage {
+    dir: PathBuf,
+    /// Keys already loaded, so games sharing a key share one copy
+    server_keys: Mutex<HashMap<String, ServerKey>>,
+    /// To the thread writing the games
+    writer: Mutex<Sender<Snapshot>>,
+}
+
+/// A job for the thread writing the games
+enum Snapshot {
+    /// Write `game.bin`, then drop the share files of `replaced_shares` the key replaced
+    Game {
+        game_id: GameId,
+        bytes: Vec<u8>,
+        replaced_shares: Vec<UserId>,
+    },
+    /// Answer once the snapshots sent before are written
+    Flush(Sender<()>),
+}
+
+impl Storage {
+    pub(crate) fn new(dir: &Path) -> Self {
+        let (writer, snapshots) = mpsc::channel();
+        let games_dir = dir.to_path_buf();
+        thread::Builder::new()
+            .name("storage".to_string())
+            .spawn(move || write_snapshots(&games_dir, snapshots))
+            .expect("The storage thread spawns");
+        Self {
+            dir: dir.to_path_buf(),
+            server_keys: Mutex::new(HashMap::new()),
+            writer: Mutex::new(writer),
+        }
+    }
+
+    fn server_key_path(&self, hash: &str) -> PathBuf {
+        self.dir.join("server_keys").join(format!("{hash}.bin"))
+    }
+
+    /// The key aggregated before from the seed and shares hashing to `hash`, if any.
+    /// A key read from disk is claimed, see [`ServerKey::claim`]
+    pub(crate) fn load_server_key(&self, hash: &str) -> Result<Option<ServerKey>, Error> {
+        let mut server_keys = self.server_keys.lock().unwrap();
+        if let Some(server_key) = server_keys.get(hash) {
+            return Ok(Some(server_key.clone()));
+        }
+        let path = self.server_key_path(hash);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let server_key = read::<ServerKey>(&path)?.claim(Some(hash))?;
+        server_keys.insert(hash.to_string(), server_key.clone());
+        Ok(Some(server_key))
+    }
+
+    /// Cache the key named `hash`. Blocks on the disk
+    pub(crate) fn save_server_key(&self, hash: &str, server_key: &ServerKey) -> Result<(), Error> {
+        let path = self.server_key_path(hash);
+        fs::create_dir_all(path.parent().expect("In the data directory"))?;
+        write_once(&path, server_key)?;
+        self.server_keys
+            .lock()
+            .unwrap()
+            .entry(hash.to_string())
+            .or_insert_with(|| server_key.clone());
+        Ok(())
+    }
+
+    fn game_dir(&self, game_id: GameId) -> PathBuf {
+        game_dir(&self.dir, game_id)
+    }
+
+    /// Save the share of `user_id` in its own file, before the game counts it. Blocks on the
+    /// disk
+    pub(crate) fn save_share(
+        &self,
+        game_id: GameId,
+        user_id: UserId,
+        sks: &ServerKeyShare,
+    ) -> Result<(), Error> {
+        let dir = self.game_dir(game_id);
+        fs::create_dir_all(&dir)?;
+        write_once(&dir.join(format!("sks_{user_id}.bin")), sks)
+    }
+
+    /// Queue a snapshot of the game for the writer. Only the serialization happens here
+    pub(crate) fn save(&self, ss: &ServerStorage) -> Result<(), Error> {
+        let bytes = bincode::serialize(ss)?;
+        // The key replaces the shares once it is cached
+        let replaced_shares = match &ss.server_key_hash {
+            Some(_) => ss.users.iter().map(|user| user.id).collect(),
+            None => vec![],
+        };
+        self.send(Snapshot::Game {
+            game_id: ss.id,
+            bytes,
+            replaced_shares,
+        })
+    }
+
+    /// Wait until the snapshots queued so far are written
+    pub(crate) fn flush(&self) -> Result<(), Error> {
+        let (done, written) = mpsc::channel();
+        self.send(Snapshot::Flush(done))?;
+        Ok(written.recv()?)
+    }
+
+    fn send(&self, snapshot: Snapshot) -> Result<(), Error> {
+        self.writer
+            .lock()
+            .unwrap()
+            .send(snapshot)
+            .map_err(|_| anyhow!("The storage thread is gone"))
+    }
+
+    /// The saved games, in ID order. A game that fails to load is logged and left out as
+    /// `None`, so it can't keep the others from loading
+    pub(crate) fn load(&self) -> Vec<Option<ServerStorage>> {
+        let mut games = vec![];
+        while self.game_dir(games.len()).join("game.bin").exists() {
+            let game_id = games.len();
+            let game = self
+                .load_game(game_id)
+                .map_err(|err| println!("Failed to load game #{game_id}: {err}"))
+                .ok();
+            games.push(game);
+        }
+        games
+    }
+
+    fn load_game(&self, game_id: GameId) -> Result<ServerStorage, Error> {
+        let dir = self.game_dir(game_id);
+        let mut ss: ServerStorage = read(&dir.join("game.bin"))?;
+        for user in ss.users.iter() {
+            if let UserStorage::Sks = user.storage {
+                let sks = read(&dir.join(format!("sks_{}.bin", user.id)))?;
+                ss.server_key_shares.insert(user.id, sks);
+            }
+        }
+
+        if let Some(hash) = &ss.server_key_hash {
+            let server_key = self
+                .load_server_key(hash)?
+                .ok_or_else(|| anyhow!("Server key {hash} of game #{game_id} is missing"))?;
+            ss.server_key = Some(server_key);
+        }
+
+        if ss.state == ServerState::RunningFhe {
+            // The run died with the previous server. The queued actions are still there
+            ss.state = ServerState::ReadyForRunning;
+        }
+        Ok(ss)
+    }
+}
+
+fn game_dir(dir: &Path, game_id: GameId) -> PathBuf {
+    dir.join(game_id.to_string())
+}
+
+/// Runs on the storage thread until the storage is dropped
+fn write_snapshots(dir: &Path, snapshots: Receiver<Snapshot>) {
+    for snapshot in snapshots {
+        match snapshot {
+            Snapshot::Game {
+                game_id,
+                bytes,
+                replaced_shares,
+            } => {
+                if let Err(err) = write_game(&game_dir(dir, game_id), &bytes, &replaced_shares) {
+                    println!("Failed to save game #{game_id}: {err}");
+                }
+            }
+            Snapshot::Flush(done) => {
+                let _ = done.send(());
+            }
+        }
+    }
+}
+
+fn write_game(dir: &Path, bytes: &[u8], replaced_shares: &[UserId]) -> Result<(), Error> {
+    fs::create_dir_all(dir)?;
+    write_bytes(&dir.join("game.bin"), bytes)?;
+    for user_id in replaced_shares {
+        let path = dir.join(format!("sks_{user_id}.bin"));
+        if path.exists() {
+            fs::remove_file(path)?;
+        }
+    }
+    Ok(())
+}
+
+/// Names the server key aggregated from `server_key_shares` under `seed`
+pub(crate) fn server_key_hash(seed: &Seed, server_key_shares: &[ServerKeyShare]) -> String {
+    let mut digest = DigestWriter(Context::new(&SHA256));
+    digest.0.update(seed);
+    for sks in server_key_shares {
+        bincode::serialize_into(&mut digest, sks).expect("Shares serialize");
+    }
+    hex::encode(digest.0.finish())
+}
+
+/// The hex SHA-256 of `bytes`
+pub(crate) fn sha256_hex(bytes: &[u8]) -> String {
+    hex::encode(digest(&SHA256, bytes))
+}
+
+/// Hashes what is written into it, so shares needn't be serialized in memory first
+struct DigestWriter(Context);
+
+impl Write for DigestWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Write aside then rename, so a crash never leaves a torn file behind. Each write has its own
+/// temporary file, so writers of the same file don't mix their bytes
+fn write(path: &Path, value: &impl Serialize) -> Result<(), Error> {
+    write_with(path, |file| Ok(bincode::serialize_into(file, value)?))
+}
+
+fn write_bytes(path: &Path, bytes: &[u8]) -> Result<(), Error> {
+    write_with(path, |file| Ok(file.write_all(bytes)?))
+}
+
+fn write_with(
+    path: &Path,
+    fill: impl FnOnce(&mut BufWriter<File>) -> Result<(), Error>,
+) -> Result<(), Error> {
+    static WRITES: AtomicU64 = AtomicU64::new(0);
+    let write_id = WRITES.fetch_add(1, Ordering::Relaxed);
+    let temp = path.with_extension(format!("{}-{write_id}.tmp", std::process::id()));
+    let mut writer = BufWriter::new(File::create(&temp)?);
+    fill(&mut writer)?;
+    writer.flush()?;
+    drop(writer);
+    fs::rename(temp, path)?;
+    Ok(())
+}
+
+fn write_once(path: &Path, value: &impl Serialize) -> Result<(), Error> {
+    if path.exists() {
+        return Ok(());
+    }
+    write(path, value)
+}
+
+fn read<T: DeserializeOwned>(path: &Path) -> Result<T, Error> {
+    let reader = BufReader::new(File::open(path)?);
+    Ok(bincode::deserialize_from(reader)?)
+}
@@ -1,26 +1,30 @@
-use crate::circuit::*;
 use crate::types::*;
 use crate::*;
 use anyhow::Error;
-use futures::future::join_all;
+use futures::StreamExt;
 use itertools::Itertools;
-use phantom_zone::MultiPartyDecryptor;
-use phantom_zone::{gen_client_key, gen_server_key_share, set_parameter_set};
-use rayon::iter::{IntoParallelRefMutIterator, ParallelIterator};
 use rocket::{
+    figment::Figment,
+    http::{Accept, ContentType, Header, Status},
     serde::{msgpack, Deserialize, Serialize},
     Build, Rocket,
 };
-use std::{collections::HashMap, time::Duration};
-use tokio::time::sleep;
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 // We're not sending the User struct in rockets. This macro is here just for Serde reasons
 #[serde(crate = "rocket::serde")]
 struct User {
     name: String,
-    // step 0: get seed
-    seed: Option<Seed>,
+    // step 0: get param
+    param: Option<Param>,
     // step 0.5: gen client key
     ck: Option<ClientKey>,
     // step 1: get userID
@@ -40,7 +44,7 @@ impl User {
     fn new(name: &str) -> Self {
         Self {
             name: name.to_string(),
-            seed: None,
+            param: None,
             ck: None,
             id: None,
             total_users: None,
@@ -51,13 +55,13 @@ impl User {
         }
     }
 
-    fn assign_seed(&mut self, seed: Seed) -> &mut Self {
-        self.seed = Some(seed);
+    fn assign_param(&mut self, param: Param) -> &mut Self {
+        self.param = Some(param);
         self
     }
 
     fn gen_client_key(&mut self) -> &mut Self {
-        self.ck = Some(gen_client_key());
+        self.ck = Some(self.param.as_ref().unwrap().backend.gen_client_key());
         self
     }
 
@@ -72,16 +76,13 @@ impl User {
     }
 
     fn assign_starting_coords(&mut self, coords: &(u8, u8)) -> &mut Self {
-        self.starting_coords = Some(coords.clone());
+        self.starting_coords = Some(*coords);
         self
     }
 
     fn gen_server_key_share(&mut self) -> &mut Self {
-        let server_key = gen_server_key_share(
-            self.id.unwrap(),
-            self.total_users.unwrap(),
-            self.ck.as_ref().unwrap(),
-        );
+        let ck = self.ck.as_ref().unwrap();
+        let server_key = ck.gen_server_key_share(self.id.unwrap(), self.total_users.unwrap());
         self.server_key = Some(server_key);
         self
     }
@@ -91,80 +92,154 @@ impl User {
         self
     }
 
-    // Populate decryption_shares with my shares
-    // fn gen_decryption_shares(&mut self) -> &mut Self {
-    //     let ck = self.ck.as_ref().expect("already exists");
-    //     let fhe_out = self.fhe_out.as_ref().expect("exists");
-    //     let my_id = self.id.expect("exists");
-
-    //     let my_decryption_shares = fhe_out.gen_decryption_shares(ck);
-    //     for (out_id, share) in my_decryption_shares.iter() {
-    //         self.decryption_shares
-    //             .insert((*out_id, my_id), share.to_vec());
-    //     }
-    //     self
-    // }
-
-    // fn get_my_shares(&self) -> Vec<AnnotatedDecryptionShare> {
-    //     let total_users = self.total_users.expect("exist");
-    //     let my_id = self.id.expect("exists");
-    //     (0..total_users)
-    //         .filter_map(|output_id| {
-    //             if output_id == my_id {
-    //                 return None;
-    //             };
-
-    //             let share = self
-    //                 .decryption_shares
-    //                 .get(&(output_id, my_id))
-    //                 .expect("exists")
-    //                 .to_owned();
-    //             Some((output_id, share))
-    //         })
-    //         .collect_vec()
-    // }
-
-    // fn decrypt_everything(&self) -> Vec<Vec<bool>> {
-    //     let total_users = self.total_users.expect("exist");
-    //     let ck = self.ck.as_ref().expect("already exists");
-    //     let co = self.fhe_out.as_ref().expect("exists");
-
-    //     let dss = (0..co.n())
-    //         .map(|output_id| {
-    //             (0..total_users)
-    //                 .map(|user_id| {
-    //                     self.decryption_shares
-    //                         .get(&(output_id, user_id))
-    //                         .expect("exists")
-    //                         .to_owned()
-    //                 })
-    //                 .collect_vec()
-    //         })
-    //         .collect_vec();
-    //     co.decrypt(ck, &dss)
-    // }
+    /// Derive my decryption shares of the output. I keep the ones of my outputs, and return
+    /// the others to submit
+    fn gen_decryption_shares(&mut self) -> Vec<AnnotatedDecryptionShare> {
+        let ck = self.ck.as_ref().expect("already exists");
+        let fhe_out = self.fhe_out.as_ref().expect("exists");
+        let user_id = self.id.expect("exists");
+        for output in fhe_out.received_by(user_id) {
+            let share = fhe_out.gen_decryption_share(ck, &output.name).unwrap();
+            self.decryption_shares
+                .insert((output.name.clone(), user_id), share);
+        }
+        fhe_out.gen_decryption_shares(ck, user_id)
+    }
+
+    fn decrypt(&self, name: &str) -> Vec<bool> {
+        let total_users = self.total_users.expect("exist");
+        let ck = self.ck.as_ref().expect("already exists");
+        let fhe_out = self.fhe_out.as_ref().expect("exists");
+        let dss = (0..total_users)
+            .map(|user_id| {
+                self.decryption_shares
+                    .get(&(name.to_string(), user_id))
+                    .expect("exists")
+                    .to_owned()
+            })
+            .collect_vec();
+        fhe_out.decrypt(ck, name, &dss).unwrap()
+    }
 }
 
 impl WebClient {
     pub(crate) async fn new_test(rocket: Rocket<Build>) -> Result<Self, Error> {
         let client = rocket::local::asynchronous::Client::tracked(rocket).await?;
-        Ok(Self::Test(Box::new(client)))
+        Ok(Self::new_local(client))
+    }
+}
+
+/// What a user does in a round of the scripted flow
+#[derive(Debug, Clone, Copy)]
+enum Step {
+    Move(Direction),
+    Lay,
+    Pickup,
+    Add(u32),
+    View,
+}
+
+/// The game played in the clear, to check what the users decrypt
+struct PlainGame {
+    board_dim: usize,
+    coords: Vec<(u8, u8)>,
+    eggs: Vec<bool>,
+    int: u32,
+}
+
+impl PlainGame {
+    fn egg_index(&self, coords: (u8, u8)) -> usize {
+        coords.0 as usize * self.board_dim + coords.1 as usize
+    }
+
+    /// Apply the step of `user_id`
+    fn act(&mut self, user_id: UserId, step: Step) {
+        let coords = self.coords[user_id];
+        match step {
+            Step::Move(direction) => {
+                let mut view = GameStateLocalView::new(coords.0, coords.1, user_id, self.board_dim);
+                view.move_player(direction);
+                self.coords[user_id] = view.coords();
+            }
+            Step::Lay | Step::Pickup => {
+                let index = self.egg_index(coords);
+                self.eggs[index] = matches!(step, Step::Lay);
+            }
+            Step::Add(n) => self.int = self.int.wrapping_add(n),
+            Step::View => {}
+        }
+    }
+
+    /// The outputs of `user_id` after their `step`, if they took one: their cell, and the
+    /// shared integer if they viewed it
+    fn outputs(&self, user_id: UserId, step: Option<Step>) -> HashMap<String, Vec<bool>> {
+        let mut outputs = HashMap::new();
+        outputs.insert(CircuitOutput::cell_name(user_id), self.cell(user_id));
+        if let Some(Step::View) = step {
+            let int = u32_to_binary::<32>(self.int).to_vec();
+            outputs.insert(CircuitOutput::int_name(user_id), int);
+        }
+        outputs
+    }
+
+    /// Which users stand on the cell of `user_id`, then whether it has an egg
+    fn cell(&self, user_id: UserId) -> Vec<bool> {
+        let coords = self.coords[user_id];
+        let mut cell = self.coords.iter().map(|p| *p == coords).collect_vec();
+        cell.push(self.eggs[self.egg_index(coords)]);
+        cell
     }
 }
 
-async fn run_flow_with_n_users(total_users: usize) -> Result<(), Error> {
-    let client = WebClient::new_test(rocket()).await.unwrap();
+/// A server whose games default to the mock backend
+async fn test_client() -> WebClient {
+    let figment: Figment = rocket::Config::figment().merge(("backend", Backend::Mock));
+    WebClient::new_test(rocket_with(figment)).await.unwrap()
+}
+
+/// Wait until the games of the server behind `client` are on disk
+async fn flush(client: &WebClient) {
+    client
+        .local()
+        .rocket()
+        .state::<Games>()
+        .unwrap()
+        .flush()
+        .await;
+}
+
+fn game_config(backend: Backend, parameter_set: ParameterSet, board_dim: usize) -> GameConfig {
+    GameConfig {
+        backend: Some(backend),
+        parameter_set: Some(parameter_set),
+        board_dim: Some(board_dim),
+        ..Default::default()
+    }
+}
+
+/// Create a game on the server of `client` and take `total_users` users through its setup,
+/// up to their first actions. The returned client has joined the game
+async fn setup_users(
+    client: &WebClient,
+    config: &GameConfig,
+    total_users: usize,
+) -> (WebClient, Vec<User>, PlainGame) {
+    let mut client = client.clone();
+    let game_id = client.create_game(config).await.unwrap();
+    client.join_game(game_id);
 
     let mut users = (0..total_users)
         .map(|i| User::new(&format!("User {i}")))
         .collect_vec();
 
-    println!("acquire seeds");
+    println!("acquire params");
 
-    // Acquire seeds
+    // Acquire params. All users share this thread, so it is set up once
+    let param = client.get_param().await.unwrap();
+    let board_dim = param.board_dim;
+    setup(&param);
     for user in users.iter_mut() {
-        let seed = client.get_seed().await.unwrap();
-        user.assign_seed(seed);
+        user.assign_param(client.get_param().await.unwrap());
         user.gen_client_key();
     }
 
@@ -176,6 +251,13 @@ async fn run_flow_with_n_users(total_users: usize) -> Result<(), Error> {
         user.set_id(reg.id);
     }
 
+    // Registration closes by itself only when the game is full
+    let dashboard = client.get_dashboard().await.unwrap();
+    if !dashboard.is_concluded() {
+        assert!(dashboard.can_start());
+        client.start_game(0).await.unwrap();
+    }
+
     for user in users.iter_mut() {
         let dashboard = client.get_dashboard().await.unwrap();
         user.set_total_users(dashboard.get_names().len());
@@ -185,9 +267,6 @@ async fn run_flow_with_n_users(total_users: usize) -> Result<(), Error> {
 
     // Generate and submit server key share
     for user in users.iter_mut() {
-        set_parameter_set(PARAMETER);
-        println!("{} Gen cipher", user.name);
-
         time!(
             || {
                 user.gen_server_key_share();
@@ -203,7 +282,7 @@ async fn run_flow_with_n_users(total_users: usize) -> Result<(), Error> {
         }
 
         println!("{} Submit server key", user.name);
-        client.submit_sks(user_id, &sks).await.unwrap();
+        client.submit_sks(user_id, sks).await.unwrap();
         // Drop here to save mem
         user.server_key = None;
     }
@@ -211,14 +290,23 @@ async fn run_flow_with_n_users(total_users: usize) -> Result<(), Error> {
     println!("user 0 calls init game");
 
     // User 0 encrypt initial eggs
-    let initial_eggs = [false; BOARD_SIZE];
+    let initial_eggs = vec![false; board_dim * board_dim];
     let ck = users[0].ck.as_ref().unwrap();
     client.init_game(ck, 0, &initial_eggs).await.unwrap();
 
     println!("users call set starting coords");
 
     // Assign starting coords
-    let users_coords = vec![(0u8, 0u8), (0u8, 0u8), (0u8, 0u8), (0u8, 0u8)];
+    let users_coords = [
+        (0u8, 0u8),
+        (1, 1),
+        (2, 2),
+        (3, 3),
+        (0, 3),
+        (3, 0),
+        (1, 2),
+        (2, 1),
+    ];
     for user in users.iter_mut() {
         user.assign_starting_coords(&users_coords[user.id.unwrap()]);
     }
@@ -226,73 +314,1214 @@ async fn run_flow_with_n_users(total_users: usize) -> Result<(), Error> {
     for (i, user) in users.iter_mut().enumerate() {
         let ck = user.ck.as_ref().unwrap();
         client
-            .set_starting_coords(ck, i, &user.starting_coords.unwrap())
+            .set_starting_coords(ck, i, &user.starting_coords.unwrap(), board_dim)
             .await
             .unwrap();
     }
 
-    println!("round 1 start");
-    println!("each user submit an action");
+    let plain = PlainGame {
+        board_dim,
+        coords: users_coords[..total_users].to_vec(),
+        eggs: vec![false; board_dim * board_dim],
+        int: 0,
+    };
+    (client, users, plain)
+}
 
-    let directions = vec![Direction::Up, Direction::Down];
-    for (i, user) in users.iter_mut().enumerate() {
-        let ck = user.ck.as_ref().unwrap();
-        if i == 0 || i == 1 {
-            client.move_player(ck, i, directions[i]).await.unwrap();
-        } else if i == 2 {
-            client.lay_egg(i).await.unwrap();
-        } else {
-            client.pickup_egg(i).await.unwrap();
+async fn request_step(client: &WebClient, user: &User, step: Step) -> Result<UserId, Error> {
+    let user_id = user.id.unwrap();
+    let ck = user.ck.as_ref().unwrap();
+    match step {
+        Step::Move(direction) => client.move_player(ck, user_id, direction).await,
+        Step::Lay => client.lay_egg(user_id).await,
+        Step::Pickup => client.pickup_egg(user_id).await,
+        Step::Add(n) => client.add_int(ck, user_id, n).await,
+        Step::View => client.view_int(user_id).await,
+    }
+}
+
+/// Every user shares the outputs of the others from the completed run, then decrypts their
+/// own with the shares of the others. Returns what each user decrypted, by output name
+async fn decrypt_round(client: &WebClient, users: &mut [User]) -> Vec<HashMap<String, Vec<bool>>> {
+    for user in users.iter_mut() {
+        let fhe_output = client.get_fhe_output().await.unwrap();
+        user.set_fhe_out(fhe_output);
+        let shares = user.gen_decryption_shares();
+        client
+            .submit_decryption_shares(user.id.unwrap(), &shares)
+            .await
+            .unwrap();
+    }
+
+    let mut decrypted = vec![];
+    for user in users.iter_mut() {
+        let my_id = user.id.unwrap();
+        let names = user
+            .fhe_out
+            .as_ref()
+            .unwrap()
+            .received_by(my_id)
+            .map(|output| output.name.clone())
+            .collect_vec();
+        for name in names.iter() {
+            for user_id in (0..user.total_users.unwrap()).filter(|user_id| *user_id != my_id) {
+                let share = client.get_decryption_share(name, user_id).await.unwrap();
+                user.decryption_shares
+                    .insert((name.clone(), user_id), share);
+            }
         }
+        decrypted.push(
+            names
+                .into_iter()
+                .map(|name| {
+                    let bits = user.decrypt(&name);
+                    (name, bits)
+                })
+                .collect(),
+        );
     }
+    decrypted
+}
+
+/// Play a scripted game in a new game on the server of `client`
+async fn run_flow_with_n_users(
+    client: &WebClient,
+    backend: Backend,
+    parameter_set: ParameterSet,
+    board_dim: usize,
+    total_users: usize,
+) -> Result<(), Error> {
+    let config = game_config(backend, parameter_set, board_dim);
+    let (client, mut users, mut plain) = setup_users(client, &config, total_users).await;
+    let param = client.get_param().await.unwrap();
+    assert_eq!(param.backend, backend);
+    assert_eq!(param.parameter_set, parameter_set);
+    assert_eq!(param.board_dim, board_dim);
+
+    // One user acts per round
+    let script = [
+        (0, Step::Move(Direction::Down)),
+        (1, Step::Move(Direction::Left)),
+        (0, Step::Lay),
+        (1, Step::Move(Direction::Up)),
+        (1, Step::Pickup),
+        (2, Step::Add(40)),
+        (3, Step::Add(2)),
+        (3, Step::View),
+    ];
 
-    client.done(0).await.unwrap();
+    for (round, (actor, step)) in script.into_iter().enumerate() {
+        let actor = actor % total_users;
+        println!("round {round}: user {actor} does {step:?}");
 
-    println!("any user calls trigger the run");
+        request_step(&client, &users[actor], step).await.unwrap();
+        client.trigger_fhe_run(actor).await.unwrap();
+        client
+            .wait_for_state(ServerState::CompletedFhe, Duration::from_secs(300))
+            .await
+            .unwrap();
 
-    // Admin runs the FHE computation
+        // Every user learns their own cell, and the actor what they asked for
+        plain.act(actor, step);
+        let decrypted = decrypt_round(&client, &mut users).await;
+        for (user_id, decrypted) in decrypted.into_iter().enumerate() {
+            let step = (user_id == actor).then_some(step);
+            let expected = plain.outputs(user_id, step);
+            assert_eq!(decrypted, expected, "User {user_id} round {round}");
+        }
+
+        for user_id in 0..total_users {
+            client.done(user_id).await.unwrap();
+        }
+    }
+    Ok(())
+}
+
+#[rocket::async_test]
+async fn mock_full_flow() {
+    // The mock backend has no global state, so flows can run again
+    let client = test_client().await;
+    for total_users in 2..=4 {
+        run_flow_with_n_users(
+            &client,
+            Backend::Mock,
+            ParameterSet::LTE4Party,
+            4,
+            total_users,
+        )
+        .await
+        .unwrap();
+    }
+    run_flow_with_n_users(&client, Backend::Mock, ParameterSet::LTE8Party, 4, 8)
+        .await
+        .unwrap();
+    for board_dim in [8, 16] {
+        run_flow_with_n_users(
+            &client,
+            Backend::Mock,
+            ParameterSet::LTE4Party,
+            board_dim,
+            4,
+        )
+        .await
+        .unwrap();
+    }
+}
+
+#[rocket::async_test]
+async fn mock_games_side_by_side() {
+    let client = test_client().await;
+    let flows = [(2, 4), (3, 8), (4, 4)].map(|(total_users, board_dim)| {
+        run_flow_with_n_users(
+            &client,
+            Backend::Mock,
+            ParameterSet::LTE4Party,
+            board_dim,
+            total_users,
+        )
+    });
+    for result in futures::future::join_all(flows).await {
+        result.unwrap();
+    }
+}
+
+#[test]
+fn gate_pools_are_shared() {
+    let pool = ParameterSet::LTE2Party.pool();
+    assert!(std::ptr::eq(pool, ParameterSet::LTE2Party.pool()));
+    assert!(!std::ptr::eq(pool, ParameterSet::LTE4Party.pool()));
+    // The workers are set up once, when the pool starts
+    assert_eq!(
+        pool.install(ParameterSet::active),
+        Some(ParameterSet::LTE2Party)
+    );
+}
+
+#[test]
+fn gate_pools_run_concurrent_games() {
+    use rayon::prelude::*;
+    let (tx, rx) = std::sync::mpsc::channel();
+    for game in 0..4 {
+        let tx = tx.clone();
+        std::thread::spawn(move || {
+            Backend::PhantomZone.init_thread(ParameterSet::LTE4Party);
+            // Each game runs its actions on the pool, and each circuit installs the pool again
+            let sums = ParameterSet::install(|| {
+                (0..8)
+                    .into_par_iter()
+                    .map(|i| {
+                        ParameterSet::install(|| {
+                            assert!(ParameterSet::LTE4Party
+                                .pool()
+                                .current_thread_index()
+                                .is_some());
+                            let out = compiled::add_to_int(
+                                &u32_to_binary::<32>(game),
+                                &u32_to_binary::<32>(i),
+                            );
+                            binary_to_u32(&out)
+                        })
+                    })
+                    .collect::<Vec<_>>()
+            });
+            tx.send((game, sums)).unwrap();
+        });
+    }
+    for _ in 0..4 {
+        let (game, sums) = rx
+            .recv_timeout(Duration::from_secs(60))
+            .expect("Games finish on the shared pool");
+        assert_eq!(sums, (0..8).map(|i| game + i).collect_vec());
+    }
+}
+
+#[test]
+fn parameter_set_for_players() {
+    assert_eq!(ParameterSet::for_players(2), Some(ParameterSet::LTE2Party));
+    assert_eq!(ParameterSet::for_players(3), Some(ParameterSet::LTE4Party));
+    assert_eq!(ParameterSet::for_players(5), Some(ParameterSet::LTE8Party));
+    assert_eq!(ParameterSet::for_players(9), None);
+}
+
+#[rocket::async_test]
+async fn start_game_needs_enough_players() {
+    let mut client = test_client().await;
+    let game_id = client.create_game(&GameConfig::default()).await.unwrap();
+    client.join_game(game_id);
+    client.register("alice").await.unwrap();
+    // Too early rather than wrong, so it is a conflict
+    let err = client.start_game(0).await.unwrap_err();
+    assert_eq!(err.downcast_ref::<ResponseError>().unwrap().status, 409);
+    client.register("bob").await.unwrap();
+    client.start_game(0).await.unwrap();
+    assert!(client.get_dashboard().await.unwrap().is_concluded());
+    assert!(client.register("carlos").await.is_err());
+}
+
+#[rocket::async_test]
+async fn games_are_separate() {
+    let mut client = test_client().await;
+    assert!(client.get_dashboard().await.is_err(), "No game joined");
+    client.join_game(0);
+    assert!(client.get_dashboard().await.is_err(), "No game created");
+
+    let invalid = GameConfig {
+        board_dim: Some(5),
+        ..Default::default()
+    };
+    let err = client.create_game(&invalid).await.unwrap_err();
+    assert_eq!(err.downcast_ref::<ResponseError>().unwrap().status, 400);
+
+    let mut other = client.clone();
+    for client in [&mut client, &mut other] {
+        let game_id = client.create_game(&GameConfig::default()).await.unwrap();
+        client.join_game(game_id);
+    }
+    assert_ne!(client.game_id(), other.game_id());
+    assert_ne!(
+        client.get_param().await.unwrap().seed,
+        other.get_param().await.unwrap().seed
+    );
+
+    client.register("alice").await.unwrap();
+    assert_eq!(client.get_dashboard().await.unwrap().get_names().len(), 1);
+    assert!(other.get_dashboard().await.unwrap().get_names().is_empty());
+}
+
+#[rocket::async_test]
+async fn second_phantom_zone_game_needs_the_cached_key() {
+    let client = test_client().await;
+    let config = |seed| GameConfig {
+        backend: Some(Backend::PhantomZone),
+        seed,
+        ..Default::default()
+    };
+    let mut first = client.clone();
+    first.join_game(client.create_game(&config(None)).await.unwrap());
+    let seed = first.get_param().await.unwrap().seed;
+
+    // The process holds one phantom-zone server key, and without a data directory no other
+    // game can load it
+    for seed in [[7; 32], seed] {
+        let err = client.create_game(&config(Some(seed))).await.unwrap_err();
+        assert!(err.to_string().contains("for game #0"), "{err}");
+    }
+    client.create_game(&GameConfig::default()).await.unwrap();
+}
+
+#[rocket::async_test]
+async fn users_cannot_act_as_each_other() {
+    let mut client = test_client().await;
+    let game_id = client.create_game(&GameConfig::default()).await.unwrap();
+    client.join_game(game_id);
+    client.register("alice").await.unwrap();
+    client.register("bob").await.unwrap();
+    assert_ne!(client.token(0), client.token(1));
+
+    // Alice can't start the game as bob
+    let bob = client.token(1).unwrap();
+    client.set_token(1, &client.token(0).unwrap()).unwrap();
+    assert!(client.start_game(1).await.is_err());
+    assert!(!client.get_dashboard().await.unwrap().is_concluded());
+
+    client.set_token(1, &bob).unwrap();
+    client.start_game(1).await.unwrap();
+}
+
+#[rocket::async_test]
+async fn missing_token_is_json() {
+    let mut client = test_client().await;
+    let game_id = client.create_game(&GameConfig::default()).await.unwrap();
+    client.join_game(game_id);
+    client.register("alice").await.unwrap();
+
+    let response = client
+        .local()
+        .post(format!("/games/{game_id}/start_game/0"))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Unauthorized);
+    assert_eq!(response.content_type(), Some(ContentType::JSON));
+    assert_eq!(
+        response.into_string().await.unwrap(),
+        crate::types::Error::MissingToken.to_string()
+    );
+}
+
+fn simultaneous(deadline: Option<u64>) -> GameConfig {
+    GameConfig {
+        round_mode: Some(RoundMode::Simultaneous { deadline }),
+        ..Default::default()
+    }
+}
+
+#[rocket::async_test]
+async fn simultaneous_round_runs_once() {
+    let client = test_client().await;
+    let (client, mut users, mut plain) = setup_users(&client, &simultaneous(None), 3).await;
+
+    let steps = [Step::Move(Direction::Right), Step::Lay, Step::View];
+    for (user_id, step) in steps.into_iter().enumerate() {
+        assert_eq!(
+            client.trigger_fhe_run(user_id).await.unwrap(),
+            ServerState::ReadyForActions
+        );
+        request_step(&client, &users[user_id], step).await.unwrap();
+        plain.act(user_id, step);
+    }
+    // One action each
+    assert!(request_step(&client, &users[0], Step::Lay).await.is_err());
+
+    client
+        .wait_for_state(ServerState::CompletedFhe, Duration::from_secs(10))
+        .await
+        .unwrap();
+    let expected = steps
+        .into_iter()
+        .enumerate()
+        .map(|(user_id, step)| plain.outputs(user_id, Some(step)))
+        .collect_vec();
+    assert_eq!(decrypt_round(&client, &mut users).await, expected);
+}
+
+#[rocket::async_test]
+async fn simultaneous_round_closes_at_deadline() {
+    let client = test_client().await;
+    let (client, mut users, mut plain) = setup_users(&client, &simultaneous(Some(1)), 2).await;
+
+    let step = Step::Move(Direction::Down);
+    request_step(&client, &users[1], step).await.unwrap();
+    plain.act(1, step);
+
+    // Nobody waits for user 0
+    client
+        .wait_for_state(ServerState::CompletedFhe, Duration::from_secs(10))
+        .await
+        .unwrap();
+    let expected = vec![plain.outputs(0, None), plain.outputs(1, Some(step))];
+    assert_eq!(decrypt_round(&client, &mut users).await, expected);
+}
+
+#[rocket::async_test]
+async fn users_keep_their_own_share() {
+    let client = test_client().await;
+    let (client, mut users, _) = setup_users(&client, &GameConfig::default(), 2).await;
+    request_step(&client, &users[0], Step::Lay).await.unwrap();
+    client.trigger_fhe_run(0).await.unwrap();
+    client
+        .wait_for_state(ServerState::CompletedFhe, Duration::from_secs(10))
+        .await
+        .unwrap();
+
+    users[0].set_fhe_out(client.get_fhe_output().await.unwrap());
+    let ck = users[0].ck.as_ref().unwrap();
+    let cell_0 = CircuitOutput::cell_name(0);
+    let own = users[0]
+        .fhe_out
+        .as_ref()
+        .unwrap()
+        .gen_decryption_share(ck, &cell_0)
+        .unwrap();
+    assert!(client
+        .submit_decryption_shares(0, &[(cell_0.clone(), own)])
+        .await
+        .is_err());
+
+    let shares = users[0].gen_decryption_shares();
+    client.submit_decryption_shares(0, &shares).await.unwrap();
+    client
+        .get_decryption_share(&CircuitOutput::cell_name(1), 0)
+        .await
+        .unwrap();
+    assert!(client.get_decryption_share(&cell_0, 0).await.is_err());
+    assert!(client.get_decryption_share("cell_9", 0).await.is_err());
+}
+
+#[rocket::async_test]
+async fn ciphertexts_are_sent_as_msgpack() {
+    let client = test_client().await;
+    let (client, users, _) = setup_users(&client, &GameConfig::default(), 2).await;
+    request_step(&client, &users[0], Step::Lay).await.unwrap();
     client.trigger_fhe_run(0).await.unwrap();
-    while client.trigger_fhe_run(0).await.unwrap() != ServerState::CompletedFhe {
-        sleep(Duration::from_secs(1)).await
+    client
+        .wait_for_state(ServerState::CompletedFhe, Duration::from_secs(10))
+        .await
+        .unwrap();
+
+    let path = format!("/games/{}/fhe_output", client.game_id().unwrap());
+    let json = client.local().get(path.clone()).dispatch().await;
+    assert_eq!(json.content_type(), Some(ContentType::JSON));
+    let json = json.into_bytes().await.unwrap();
+    let msgpack = client
+        .local()
+        .get(path)
+        .header(Accept::MsgPack)
+        .dispatch()
+        .await;
+    assert_eq!(msgpack.content_type(), Some(ContentType::MsgPack));
+    assert!(msgpack.into_bytes().await.unwrap().len() < json.len());
+}
+
+#[rocket::async_test]
+async fn failed_run_opens_the_round_again() {
+    let client = test_client().await;
+    let (client, users, _) = setup_users(&client, &GameConfig::default(), 2).await;
+    let mut updates = client.dashboard_updates().await.unwrap();
+
+    // Actions are checked on submission, so break the game state for the circuit to panic
+    let games = client.local().rocket().state::<Games>().unwrap();
+    let game = games.get(client.game_id().unwrap()).await.unwrap();
+    let int = {
+        let mut ss = game.lock().await;
+        let game_state = ss.game_state.as_mut().unwrap();
+        let int = game_state.int.clone();
+        game_state.int.truncate(1);
+        int
+    };
+    request_step(&client, &users[0], Step::Add(1))
+        .await
+        .unwrap();
+    client.trigger_fhe_run(0).await.unwrap();
+    while let Some(dashboard) = updates.next().await {
+        if dashboard.unwrap().get_fhe_failure().is_some() {
+            break;
+        }
     }
+    // The failed run left the game state as it was
+    let mut ss = game.lock().await;
+    assert_eq!(ss.game_state.as_ref().unwrap().int.len(), 1);
+    ss.game_state.as_mut().unwrap().int = int;
+    drop(ss);
 
-    println!("users get fhe output and decrypt shares");
+    let dashboard = client.get_dashboard().await.unwrap();
+    assert!(dashboard.is_ready_for_actions(0));
+    assert!(client
+        .trigger_fhe_run(0)
+        .await
+        .unwrap_err()
+        .to_string()
+        .contains("FHE run failed"));
+    // Waiting for the run ends with the failure, not the timeout
+    assert!(client
+        .wait_for_state(ServerState::CompletedFhe, Duration::from_secs(30))
+        .await
+        .unwrap_err()
+        .to_string()
+        .contains("FHE run failed"));
 
-    // Users get FHE output, generate decryption shares, and submit decryption shares
-    for user in users.iter_mut() {
-        let fhe_output = client.get_fhe_output().await.unwrap();
+    // The game carries on
+    request_step(&client, &users[0], Step::Lay).await.unwrap();
+    client.trigger_fhe_run(0).await.unwrap();
+    let dashboard = client
+        .wait_for_state(ServerState::CompletedFhe, Duration::from_secs(10))
+        .await
+        .unwrap();
+    assert_eq!(dashboard.get_fhe_failure(), None);
+}
 
-        user.set_fhe_out(fhe_output);
-        // user.gen_decryption_shares();
+#[rocket::async_test]
+async fn follow_fhe_run_until_complete() {
+    let client = test_client().await;
+    let (client, users, _) = setup_users(&client, &GameConfig::default(), 2).await;
+
+    request_step(&client, &users[0], Step::Lay).await.unwrap();
+    client.trigger_fhe_run(0).await.unwrap();
+    let dashboard = client.follow_fhe_run().await.unwrap();
+    assert!(dashboard.is_fhe_complete());
+}
+
+#[rocket::async_test]
+async fn wrong_word_widths_are_rejected() {
+    let client = test_client().await;
+    let (client, _, _) = setup_users(&client, &GameConfig::default(), 2).await;
+
+    // One bit where the circuits expect 32
+    let action = UserAction::AddInt {
+        user_int: EncryptedWord::Mock(vec![true]),
+    };
+    let token = client.token(0).unwrap();
+    let response = client
+        .local()
+        .post(format!(
+            "/games/{}/request_action/0",
+            client.game_id().unwrap()
+        ))
+        .header(Header::new("Authorization", format!("Bearer {token}")))
+        .msgpack(&action)
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::BadRequest);
+    assert!(response
+        .into_string()
+        .await
+        .unwrap()
+        .contains("Expect 32 bits for user_int but got 1"));
+
+    // Nothing was queued, so the user can still act
+    let dashboard = client.get_dashboard().await.unwrap();
+    assert!(dashboard.is_ready_for_actions(0));
+}
+
+#[rocket::async_test]
+async fn dashboard_updates_are_pushed() {
+    let mut client = test_client().await;
+    let game_id = client.create_game(&GameConfig::default()).await.unwrap();
+    client.join_game(game_id);
+    let mut updates = client.dashboard_updates().await.unwrap();
+    let dashboard = updates.next().await.unwrap().unwrap();
+    assert!(dashboard.get_names().is_empty());
+
+    client.register("alice").await.unwrap();
+    let dashboard = updates.next().await.unwrap().unwrap();
+    assert_eq!(dashboard.get_names(), vec!["alice".to_string()]);
+}
 
-        // client
-        //     .submit_decryption_share(user.id.expect("exist now"), &&user.get_my_shares())
-        //     .await
-        //     .unwrap();
+#[rocket::async_test]
+async fn wait_returns_once_reached() {
+    let mut client = test_client().await;
+    let game_id = client.create_game(&GameConfig::default()).await.unwrap();
+    client.join_game(game_id);
+    client.register("alice").await.unwrap();
+    client.register("bob").await.unwrap();
+
+    let started = ServerState::ReadyForServerKeyShares;
+    assert!(client
+        .wait_for_state(started.clone(), Duration::ZERO)
+        .await
+        .is_err());
+    let (dashboard, start) = tokio::join!(
+        client.wait_for_state(started, Duration::from_secs(10)),
+        client.start_game(0)
+    );
+    start.unwrap();
+    assert!(dashboard.unwrap().is_concluded());
+
+    // States already passed don't block
+    client
+        .wait_for_state(ServerState::ReadyForJoining, Duration::ZERO)
+        .await
+        .unwrap();
+}
+
+#[rocket::async_test]
+async fn games_survive_restart() {
+    let data_dir = std::env::temp_dir().join(format!("chickens-{}", std::process::id()));
+    let figment = || {
+        rocket::Config::figment()
+            .merge(("backend", Backend::Mock))
+            .merge(("data_dir", data_dir.display().to_string()))
+    };
+
+    let mut client = WebClient::new_test(rocket_with(figment())).await.unwrap();
+    let game_id = client.create_game(&GameConfig::default()).await.unwrap();
+    client.join_game(game_id);
+    client.register("alice").await.unwrap();
+    client.register("bob").await.unwrap();
+    client.start_game(0).await.unwrap();
+    client.submit_sks(0, &ServerKeyShare::Mock).await.unwrap();
+    let seed = client.get_param().await.unwrap().seed;
+    flush(&client).await;
+    // Only the hashes of the tokens are saved
+    let saved = std::fs::read(data_dir.join(game_id.to_string()).join("game.bin")).unwrap();
+    let token = client.token(1).unwrap();
+    assert!(!saved
+        .windows(token.len())
+        .any(|window| window == token.as_bytes()));
+
+    let mut restarted = WebClient::new_test(rocket_with(figment())).await.unwrap();
+    restarted.join_game(game_id);
+    restarted.set_token(1, &client.token(1).unwrap()).unwrap();
+    assert_eq!(restarted.get_param().await.unwrap().seed, seed);
+    assert_eq!(
+        restarted.get_dashboard().await.unwrap().get_names(),
+        ["alice", "bob"]
+    );
+    // Bob carries on where the game left off
+    restarted
+        .submit_sks(1, &ServerKeyShare::Mock)
+        .await
+        .unwrap();
+    assert!(restarted
+        .get_dashboard()
+        .await
+        .unwrap()
+        .is_submit_sks_complete());
+
+    std::fs::remove_dir_all(&data_dir).unwrap();
+}
+
+#[rocket::async_test]
+async fn unreadable_games_are_skipped() {
+    let data_dir = std::env::temp_dir().join(format!("chickens-bad-{}", std::process::id()));
+    let figment = || {
+        rocket::Config::figment()
+            .merge(("backend", Backend::Mock))
+            .merge(("data_dir", data_dir.display().to_string()))
+    };
+    let client = WebClient::new_test(rocket_with(figment())).await.unwrap();
+    for _ in 0..2 {
+        client.create_game(&GameConfig::default()).await.unwrap();
     }
+    flush(&client).await;
+    std::fs::write(data_dir.join("0").join("game.bin"), b"torn").unwrap();
 
-    // TODO: should not hard code this
-    let correct_ouput = [
-        [true, false, false, false, false],
-        [false, true, false, false, false],
-        [false, false, true, true, false],
-        [false, false, true, true, false],
-    ];
+    let mut restarted = WebClient::new_test(rocket_with(figment())).await.unwrap();
+    restarted.join_game(0);
+    assert!(restarted.get_dashboard().await.is_err());
+    restarted.join_game(1);
+    restarted.get_dashboard().await.unwrap();
+    // The skipped game keeps its ID
+    assert_eq!(
+        restarted.create_game(&GameConfig::default()).await.unwrap(),
+        2
+    );
 
-    // Each user decrypt thier own cell
-    println!("Users decrypt their own cell");
-    // for user in users {
-    //     let decrypted_outs = user.decrypt_everything();
-    //     println!("{} sees {:?}", user.name, decrypted_outs);
-    //     assert_eq!(decrypted_outs, correct_ouput);
-    // }
-    Ok(())
+    std::fs::remove_dir_all(&data_dir).unwrap();
 }
 
 #[rocket::async_test]
+async fn sks_upload_resumes() {
+    let mut client = test_client().await;
+    let game_id = client.create_game(&GameConfig::default()).await.unwrap();
+    client.join_game(game_id);
+    client.register("alice").await.unwrap();
+    client.register("bob").await.unwrap();
+    client.start_game(0).await.unwrap();
+
+    // Alice's connection drops after the first bytes
+    let (start, body) = SksUploadStart::encode(&ServerKeyShare::Mock, Compression::Zstd).unwrap();
+    let path = format!("/games/{game_id}/sks_upload/0");
+    let bearer = Header::new(
+        "Authorization",
+        format!("Bearer {}", client.token(0).unwrap()),
+    );
+    let response = client
+        .local()
+        .post(path.clone())
+        .header(bearer.clone())
+        .json(&start)
+        .dispatch()
+        .await;
+    assert_eq!(response.into_json::<u64>().await, Some(0));
+    let response = client
+        .local()
+        .post(format!("{path}/chunk/0"))
+        .header(bearer.clone())
+        .body(&body[..4])
+        .dispatch()
+        .await;
+    assert_eq!(response.into_json::<u64>().await, Some(4));
+    // Chunks follow each other
+    let response = client
+        .local()
+        .post(format!("{path}/chunk/0"))
+        .header(bearer)
+        .body(&body[4..])
+        .dispatch()
+        .await;
+    assert_ne!(response.status(), Status::Ok);
+
+    // Only the rest is sent again
+    client.submit_sks(0, &ServerKeyShare::Mock).await.unwrap();
+    client.submit_sks(1, &ServerKeyShare::Mock).await.unwrap();
+    assert!(client
+        .get_dashboard()
+        .await
+        .unwrap()
+        .is_submit_sks_complete());
+}
+
+#[rocket::async_test]
+async fn sks_upload_survives_failed_finish() {
+    let mut client = test_client().await;
+    let game_id = client.create_game(&GameConfig::default()).await.unwrap();
+    client.join_game(game_id);
+    client.register("alice").await.unwrap();
+    client.register("bob").await.unwrap();
+    client.start_game(0).await.unwrap();
+
+    let (start, body) = SksUploadStart::encode(&ServerKeyShare::Mock, Compression::Zstd).unwrap();
+    let path = format!("/games/{game_id}/sks_upload/0");
+    let bearer = Header::new(
+        "Authorization",
+        format!("Bearer {}", client.token(0).unwrap()),
+    );
+    let post = |path: String, body: &[u8]| {
+        client
+            .local()
+            .post(path)
+            .header(bearer.clone())
+            .body(body)
+            .dispatch()
+    };
+    post(path.clone(), &serde_json::to_vec(&start).unwrap()).await;
+    post(format!("{path}/chunk/0"), &body[..4]).await;
+
+    // Finishing early fails the size and hash checks
+    let response = post(format!("{path}/finish"), &[]).await;
+    assert_ne!(response.status(), Status::Ok);
+    // They are kept, so only the missing chunk is sent
+    let response = post(format!("{path}/chunk/4"), &body[4..]).await;
+    assert_eq!(response.into_json::<u64>().await, Some(body.len() as u64));
+    let response = post(format!("{path}/finish"), &[]).await;
+    assert_eq!(response.status(), Status::Ok);
+    client.submit_sks(1, &ServerKeyShare::Mock).await.unwrap();
+    assert!(client
+        .get_dashboard()
+        .await
+        .unwrap()
+        .is_submit_sks_complete());
+}
+
+#[rocket::async_test]
+async fn sks_upload_is_bounded() {
+    let figment = rocket::Config::figment()
+        .merge(("backend", Backend::Mock))
+        .merge(("max_sks_size", "1 KB"));
+    let mut client = WebClient::new_test(rocket_with(figment)).await.unwrap();
+    let game_id = client.create_game(&GameConfig::default()).await.unwrap();
+    client.join_game(game_id);
+    client.register("alice").await.unwrap();
+    client.register("bob").await.unwrap();
+    client.start_game(0).await.unwrap();
+
+    let path = format!("/games/{game_id}/sks_upload/0");
+    let bearer = Header::new(
+        "Authorization",
+        format!("Bearer {}", client.token(0).unwrap()),
+    );
+    let post = |path: String, body: &[u8]| {
+        client
+            .local()
+            .post(path)
+            .header(bearer.clone())
+            .body(body)
+            .dispatch()
+    };
+    let announce = |body: &[u8]| SksUploadStart {
+        size: body.len() as u64,
+        sha256: crate::storage::sha256_hex(body),
+        compression: Compression::Zstd,
+    };
+
+    // Too large as announced
+    let start = announce(&[0; 2000]);
+    let response = post(path.clone(), &serde_json::to_vec(&start).unwrap()).await;
+    assert_eq!(response.status(), Status::PayloadTooLarge);
+
+    // A few bytes decompressing past the limit
+    let bomb = zstd::encode_all(&[0u8; 1_000_000][..], 0).unwrap();
+    assert!(bomb.len() < 1000);
+    let start = announce(&bomb);
+    post(path.clone(), &serde_json::to_vec(&start).unwrap()).await;
+    post(format!("{path}/chunk/0"), &bomb).await;
+    let response = post(format!("{path}/finish"), &[]).await;
+    assert_eq!(response.status(), Status::PayloadTooLarge);
+
+    client.submit_sks(0, &ServerKeyShare::Mock).await.unwrap();
+}
+
+#[rocket::async_test]
+async fn server_key_is_cached() {
+    let data_dir = std::env::temp_dir().join(format!("chickens-keys-{}", std::process::id()));
+    let figment = rocket::Config::figment()
+        .merge(("backend", Backend::Mock))
+        .merge(("data_dir", data_dir.display().to_string()));
+    let mut client = WebClient::new_test(rocket_with(figment)).await.unwrap();
+
+    let mut seed = None;
+    for _ in 0..2 {
+        let config = GameConfig {
+            max_players: Some(2),
+            seed,
+            ..Default::default()
+        };
+        let game_id = client.create_game(&config).await.unwrap();
+        client.join_game(game_id);
+        seed = Some(client.get_param().await.unwrap().seed);
+        client.register("alice").await.unwrap();
+        client.register("bob").await.unwrap();
+        for user_id in 0..2 {
+            client
+                .submit_sks(user_id, &ServerKeyShare::Mock)
+                .await
+                .unwrap();
+        }
+        assert!(client
+            .get_dashboard()
+            .await
+            .unwrap()
+            .is_submit_sks_complete());
+
+        // The key replaces the shares on disk
+        flush(&client).await;
+        let game_dir = data_dir.join(game_id.to_string());
+        assert!(!game_dir.join("sks_0.bin").exists());
+    }
+    // The same seed and shares name the same cached key
+    assert_eq!(
+        std::fs::read_dir(data_dir.join("server_keys"))
+            .unwrap()
+            .count(),
+        1
+    );
+
+    std::fs::remove_dir_all(&data_dir).unwrap();
+}
+
+#[rocket::async_test]
+#[ignore = "Real FHE takes minutes, and the CRS seed can only be set once per process"]
 async fn full_flow() {
-    // Need to fix the global variable thing to allow multiple flow run
-    // run_flow_with_n_users(2).await.unwrap();
-    // run_flow_with_n_users(3).await.unwrap();
-    run_flow_with_n_users(4).await.unwrap();
+    let data_dir = std::env::temp_dir().join(format!("chickens-fhe-{}", std::process::id()));
+    let figment = || {
+        rocket::Config::figment()
+            .merge(("backend", Backend::Mock))
+            .merge(("data_dir", data_dir.display().to_string()))
+    };
+    let client = WebClient::new_test(rocket_with(figment())).await.unwrap();
+    run_flow_with_n_users(&client, Backend::PhantomZone, ParameterSet::LTE4Party, 4, 4)
+        .await
+        .unwrap();
+    flush(&client).await;
+
+    // The restarted server claims a copy of the key it already holds, from the cache
+    let mut restarted = WebClient::new_test(rocket_with(figment())).await.unwrap();
+    restarted.join_game(0);
+    assert!(restarted.get_dashboard().await.is_ok());
+    let config = |seed| GameConfig {
+        backend: Some(Backend::PhantomZone),
+        seed,
+        ..Default::default()
+    };
+    assert!(restarted.create_game(&config(None)).await.is_err());
+    // The same seed and shares load the cached key
+    let seed = restarted.get_param().await.unwrap().seed;
+    restarted.create_game(&config(Some(seed))).await.unwrap();
+
+    std::fs::remove_dir_all(&data_dir).unwrap();
+}
+
+const DIRECTIONS: [Direction; 4] = [
+    Direction::Up,
+    Direction::Down,
+    Direction::Left,
+    Direction::Right,
+];
+
+/// Egg boards to check lay/pickup against, one bit per cell
+fn egg_boards(board_dim: usize) -> Vec<Vec<bool>> {
+    let cells = board_dim * board_dim;
+    let patterns: [fn(usize) -> bool; 6] = [
+        |_| false,
+        |_| true,
+        |i| i % 2 == 1,
+        |i| i % 2 == 0,
+        |i| (i * 7 + 3) % 5 < 2,
+        |i| (i / 4) % 2 == 1,
+    ];
+    patterns
+        .iter()
+        .map(|pattern| (0..cells).map(pattern).collect_vec())
+        .collect_vec()
+}
+
+fn all_coords(board_dim: usize) -> impl Iterator<Item = (u8, u8)> {
+    (0..board_dim as u8).cartesian_product(0..board_dim as u8)
+}
+
+fn plain_coords(coords: (u8, u8), board_dim: usize) -> Vec<bool> {
+    coords_to_binary::<16>(coords.0, coords.1, board_dim).to_vec()
+}
+
+/// Walk the board from (0, 0) to lay the eggs of `board`, then go to `coords`
+fn view_with_eggs(coords: (u8, u8), board: &[bool], board_dim: usize) -> GameStateLocalView {
+    let mut view = GameStateLocalView::new(0, 0, 0, board_dim);
+    for _ in 0..board_dim {
+        for _ in 0..board_dim {
+            let (x, y) = view.coords();
+            if board[x as usize * board_dim + y as usize] {
+                view.lay();
+            }
+            view.move_player(Direction::Right);
+        }
+        view.move_player(Direction::Down);
+    }
+    for _ in 0..coords.0 {
+        view.move_player(Direction::Down);
+    }
+    for _ in 0..coords.1 {
+        view.move_player(Direction::Right);
+    }
+    assert_eq!(view.coords(), coords);
+    view
+}
+
+fn view_eggs(view: &GameStateLocalView) -> Vec<bool> {
+    view.eggs().iter().flatten().copied().collect_vec()
+}
+
+#[test]
+fn plain_move_player() {
+    for dim in compiled::BOARD_DIMS {
+        for coords in all_coords(dim) {
+            for direction in DIRECTIONS {
+                let mut view = GameStateLocalView::new(coords.0, coords.1, 0, dim);
+                view.move_player(direction);
+
+                let direction_bits = u8_to_binary::<8>(direction as u8);
+                let out = compiled::move_player(dim, &plain_coords(coords, dim), &direction_bits);
+                assert_eq!(
+                    out,
+                    plain_coords(view.coords(), dim),
+                    "{dim} {coords:?} {direction:?}"
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn plain_lay_egg() {
+    for dim in compiled::BOARD_DIMS {
+        for coords in all_coords(dim) {
+            for (b, board) in egg_boards(dim).iter().enumerate() {
+                let mut view = view_with_eggs(coords, board, dim);
+                assert_eq!(&view_eggs(&view), board);
+                view.lay();
+
+                let out = compiled::lay_egg(dim, &plain_coords(coords, dim), board);
+                assert_eq!(out, view_eggs(&view), "{dim} {coords:?} board {b}");
+            }
+        }
+    }
+}
+
+#[test]
+fn plain_pickup_egg() {
+    for dim in compiled::BOARD_DIMS {
+        for coords in all_coords(dim) {
+            for (b, board) in egg_boards(dim).iter().enumerate() {
+                let mut view = view_with_eggs(coords, board, dim);
+                view.pickup();
+
+                let out = compiled::pickup_egg(dim, &plain_coords(coords, dim), board);
+                assert_eq!(out, view_eggs(&view), "{dim} {coords:?} board {b}");
+            }
+        }
+    }
+}
+
+#[test]
+fn plain_get_cell() {
+    for dim in compiled::BOARD_DIMS {
+        for coords in all_coords(dim) {
+            for (b, board) in egg_boards(dim).iter().enumerate() {
+                let mut view = view_with_eggs(coords, board, dim);
+                let egg = *view.get_egg();
+                // Every cell on the 4 x 4 board, fewer on the larger ones
+                for other in all_coords(dim).step_by(dim / 4) {
+                    let last = (dim as u8 - 1, dim as u8 - 1);
+                    let players = [coords, other, (0, 0), last];
+                    let players_bits = players
+                        .iter()
+                        .flat_map(|p| plain_coords(*p, dim))
+                        .collect_vec();
+
+                    let out =
+                        compiled::get_cell(dim, &plain_coords(coords, dim), board, &players_bits);
+                    let mut expected = players.iter().map(|p| *p == coords).collect_vec();
+                    expected.push(egg);
+                    assert_eq!(out, expected, "{dim} {coords:?} {other:?} board {b}");
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn plain_get_cell_players() {
+    let dim = DEFAULT_BOARD_DIM;
+    let others = all_coords(dim).collect_vec();
+    let boards = egg_boards(dim);
+    for coords in all_coords(dim) {
+        for n in 1..=2 * compiled::GET_CELL_PLAYERS + 1 {
+            let players = others.iter().cycle().skip(n).take(n).copied().collect_vec();
+            let players_bits = players
+                .iter()
+                .flat_map(|p| plain_coords(*p, dim))
+                .collect_vec();
+            let board = &boards[n % boards.len()];
+            let mut view = view_with_eggs(coords, board, dim);
+
+            let out = compiled::get_cell(dim, &plain_coords(coords, dim), board, &players_bits);
+            let mut expected = players.iter().map(|p| *p == coords).collect_vec();
+            expected.push(*view.get_egg());
+            assert_eq!(out, expected, "{coords:?} {n} players");
+        }
+    }
+}
+
+#[test]
+fn plain_gates_are_reported() {
+    for dim in compiled::BOARD_DIMS {
+        let gates = Arc::new(AtomicUsize::new(0));
+        let counter = gates.clone();
+        let report: compiled::GateReport = Arc::new(move |n| {
+            counter.fetch_add(n, Ordering::Relaxed);
+        });
+        let coords = plain_coords((0, 0), dim);
+        let board = &egg_boards(dim)[0];
+        let players = [(0, 0), (1, 1), (0, 0), (1, 1), (0, 0)]
+            .iter()
+            .flat_map(|p| plain_coords(*p, dim))
+            .collect_vec();
+        compiled::report_gates(report, || {
+            let direction = u8_to_binary::<8>(Direction::Up as u8);
+            compiled::move_player(dim, &coords, &direction);
+            compiled::lay_egg(dim, &coords, board);
+            compiled::pickup_egg(dim, &coords, board);
+            compiled::get_cell(dim, &coords, board, &players);
+        });
+        let expected = compiled::move_player_gates(dim)
+            + compiled::lay_egg_gates(dim)
+            + compiled::pickup_egg_gates(dim)
+            + compiled::get_cell_gates(dim, 5);
+        assert_eq!(gates.load(Ordering::Relaxed), expected, "{dim}");
+    }
+}
+
+/// A plain bit, and the bootstrap depth computing it under phantom-zone: every two-input gate
+/// bootstraps after its inputs, and only NOT is free
+#[derive(Debug, Clone, Copy)]
+struct Counted(bool, usize);
+
+impl Counted {
+    fn gate(&self, rhs: &Self, bit: bool) -> Self {
+        Counted(bit, self.1.max(rhs.1) + 1)
+    }
+}
+
+impl compiled::GateOps for Counted {
+    fn and2(&self, rhs: &Self) -> Self {
+        self.gate(rhs, self.0 & rhs.0)
+    }
+    fn nand2(&self, rhs: &Self) -> Self {
+        self.gate(rhs, !(self.0 & rhs.0))
+    }
+    fn or2(&self, rhs: &Self) -> Self {
+        self.gate(rhs, self.0 | rhs.0)
+    }
+    fn nor2(&self, rhs: &Self) -> Self {
+        self.gate(rhs, !(self.0 | rhs.0))
+    }
+    fn xor2(&self, rhs: &Self) -> Self {
+        self.gate(rhs, self.0 ^ rhs.0)
+    }
+    fn xnor2(&self, rhs: &Self) -> Self {
+        self.gate(rhs, !(self.0 ^ rhs.0))
+    }
+    fn inv(&self) -> Self {
+        Counted(!self.0, self.1)
+    }
+    fn constant(value: bool, _like: &Self) -> Self {
+        Counted(value, 0)
+    }
+}
+
+#[test]
+fn plain_mux2() {
+    use compiled::GateOps;
+    for bits in 0..8u8 {
+        let [a, b, s] = u8_to_binary::<3>(bits);
+        let expected = if s { b } else { a };
+        assert_eq!(s.mux2(&a, &b), expected, "{a} {b} {s}");
+
+        let [a, b, s] = [a, b, s].map(|bit| Counted(bit, 0));
+        let out = s.mux2(&a, &b);
+        assert_eq!(out.0, expected);
+        assert_eq!(out.1, 2, "Two ANDs side by side, then the OR");
+    }
+}
+
+#[test]
+fn plain_add_to_int() {
+    let cases = [
+        (0, 0),
+        (1, 2),
+        (12345, 67890),
+        (u32::MAX, 1),
+        (u32::MAX, u32::MAX),
+    ];
+    for (state, user_int) in cases {
+        let out = compiled::add_to_int(&u32_to_binary::<32>(user_int), &u32_to_binary::<32>(state));
+        assert_eq!(
+            binary_to_u32(&out),
+            state.wrapping_add(user_int),
+            "{state} + {user_int}"
+        );
+    }
+}
+
+#[test]
+fn netlist_constants() {
+    // A half adder from a full adder with carry-in tied low
+    let netlist = compiled::Netlist::from_json(
+        r#"{
+            "args": [2],
+            "outputs": 2,
+            "levels": [
+                {
+                    "gates": [
+                        {"id": 0, "is_output": false, "cell": "XOR2", "inputs": [{"Arg": [0, 0]}, {"Arg": [0, 1]}]},
+                        {"id": 1, "is_output": false, "cell": "AND2", "inputs": [{"Arg": [0, 0]}, {"Arg": [0, 1]}]}
+                    ],
+                    "prune": []
+                },
+                {
+                    "gates": [
+                        {"id": 0, "is_output": true, "cell": "XOR2", "inputs": [{"Tv": 0}, {"Cst": false}]},
+                        {"id": 2, "is_output": false, "cell": "AND2", "inputs": [{"Tv": 0}, {"Cst": false}]}
+                    ],
+                    "prune": [0]
+                },
+                {
+                    "gates": [
+                        {"id": 1, "is_output": true, "cell": "OR2", "inputs": [{"Tv": 1}, {"Tv": 2}]}
+                    ],
+                    "prune": [1, 2]
+                }
+            ]
+        }"#,
+    )
+    .unwrap();
+    for (a, b) in [false, true].into_iter().cartesian_product([false, true]) {
+        assert_eq!(netlist.evaluate(&[&[a, b]]), vec![a ^ b, a & b]);
+    }
+}
+
+#[test]
+fn netlist_mux() {
+    let netlist = compiled::Netlist::from_json(
+        r#"{
+            "args": [3],
+            "outputs": 4,
+            "levels": [
+                {
+                    "gates": [
+                        {"id": 0, "is_output": true, "cell": "MUX2", "inputs": [{"Arg": [0, 0]}, {"Arg": [0, 1]}, {"Arg": [0, 2]}]},
+                        {"id": 1, "is_output": true, "cell": "MUX2", "inputs": [{"Cst": false}, {"Arg": [0, 1]}, {"Arg": [0, 2]}]},
+                        {"id": 2, "is_output": true, "cell": "MUX2", "inputs": [{"Arg": [0, 0]}, {"Cst": true}, {"Arg": [0, 2]}]},
+                        {"id": 3, "is_output": true, "cell": "MUX2", "inputs": [{"Arg": [0, 0]}, {"Arg": [0, 1]}, {"Cst": true}]}
+                    ],
+                    "prune": []
+                }
+            ]
+        }"#,
+    )
+    .unwrap();
+    for bits in 0..8u8 {
+        let [a, b, s] = u8_to_binary::<3>(bits);
+        let expected = vec![if s { b } else { a }, s && b, s || a, b];
+        assert_eq!(netlist.evaluate(&[&[a, b, s]]), expected, "{a} {b} {s}");
+    }
 }
@@ -1,45 +1,96 @@
-use crate::circuit::PARAMETER;
-use crate::dashboard::{Dashboard, RegisteredUser};
-use itertools::Itertools;
-use phantom_zone::{
-    evaluator::NonInteractiveMultiPartyCrs,
-    keys::CommonReferenceSeededNonInteractiveMultiPartyServerKeyShare, parameters::BoolParameters,
-    set_parameter_set, Encryptor, FheBool, KeySwitchWithId, MultiPartyDecryptor,
-    NonInteractiveSeededFheBools, SampleExtractor,
+use crate::backend::{derive_server_key, ServerKey};
+pub use crate::backend::{
+    Backend, Ciphertext, ClientKey, EncryptedWord, ParameterSet, ServerKeyShare,
 };
-use rocket::serde::{Deserialize, Serialize};
-use rocket::tokio::sync::Mutex;
-use rocket::Responder;
+use crate::compiled::report_gates;
+use crate::dashboard::{Dashboard, RegisteredUser, Registration, StepProgress};
+use crate::storage::{server_key_hash, sha256_hex, Storage};
+use itertools::Itertools;
+use rand::{thread_rng, RngCore};
+use ring::constant_time::verify_slices_are_equal;
+use rocket::serde::{msgpack, Deserialize, Serialize};
+use rocket::tokio::sync::{watch, Mutex};
+use rocket::{FromFormField, Responder};
 use std::collections::HashMap;
 use std::fmt::Debug;
 use std::fmt::Display;
-use std::sync::Arc;
+use std::io::Read;
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, SystemTime};
 use tabled::Table;
 use thiserror::Error;
 
-pub type ClientKey = phantom_zone::ClientKey;
 pub type UserId = usize;
 
-pub(crate) type Seed = [u8; 32];
-pub(crate) type ServerKeyShare = CommonReferenceSeededNonInteractiveMultiPartyServerKeyShare<
-    Vec<Vec<u64>>,
-    BoolParameters<u64>,
-    NonInteractiveMultiPartyCrs<Seed>,
->;
+pub type GameId = usize;
+
+pub type Seed = [u8; 32];
+
+pub type Word = Vec<Ciphertext>;
+
+/// Public parameters every user needs before generating keys
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct Param {
+    pub backend: Backend,
+    pub parameter_set: ParameterSet,
+    pub seed: Seed,
+    /// The board is `board_dim` x `board_dim`
+    pub board_dim: usize,
+    pub round_mode: RoundMode,
+}
+
+impl Param {
+    /// Set the thread-local parameters announced by the server
+    pub(crate) fn init_thread(&self) {
+        self.backend.init_thread(self.parameter_set);
+    }
+}
+
+/// How the players of a game take their actions
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub enum RoundMode {
+    /// The first action of a round is its only one
+    #[default]
+    Turns,
+    /// Every player acts, and one run evaluates all the actions.
+    /// With a `deadline` in seconds, the round also closes that long after it opened, without
+    /// the players who didn't act
+    Simultaneous { deadline: Option<u64> },
+}
 
-pub type Word = Vec<FheBool>;
-pub(crate) type EncryptedWord = NonInteractiveSeededFheBools<Vec<u64>, Seed>;
+/// Settings for a new game. Unset fields fall back to the server config
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct GameConfig {
+    pub backend: Option<Backend>,
+    pub parameter_set: Option<ParameterSet>,
+    pub min_players: Option<usize>,
+    pub max_players: Option<usize>,
+    pub board_dim: Option<usize>,
+    /// Reuse the seed of an earlier game, so its players can submit the same server key shares
+    /// and the server loads the cached key instead of aggregating it again
+    pub seed: Option<Seed>,
+    /// Defaults to [`RoundMode::Turns`]
+    pub round_mode: Option<RoundMode>,
+}
 
 /// Decryption share for a word from one user.
 pub type DecryptionShare = Vec<u64>;
 
-/// Decryption share with output id
-pub(crate) type AnnotatedDecryptionShare = (usize, DecryptionShare);
+/// Decryption share with the name of the output it decrypts
+pub(crate) type AnnotatedDecryptionShare = (String, DecryptionShare);
+
+/// Board dimension when the server config doesn't set `board_dim`
+pub const DEFAULT_BOARD_DIM: usize = 4;
 
-pub const BOARD_DIM: usize = 4;
-pub const BOARD_SIZE: usize = BOARD_DIM * BOARD_DIM;
+/// Bits of the words in a [`UserAction`]
+pub(crate) const INT_BITS: usize = 32;
+pub(crate) const COORDS_BITS: usize = 16;
+pub(crate) const DIRECTION_BITS: usize = 8;
 
-#[derive(Copy, Clone)]
+#[derive(Debug, Copy, Clone)]
 #[repr(u8)]
 pub enum Direction {
     Up = 0,
@@ -48,37 +99,42 @@ pub enum Direction {
     Right,
 }
 
-fn u8_to_binary<const N: usize>(v: u8) -> [bool; N] {
+pub(crate) fn u8_to_binary<const N: usize>(v: u8) -> [bool; N] {
     assert!((v as u16) < 2u16.pow(N as u32));
-    let mut result = [false; N];
-    for i in 0..N {
-        if (v >> i) & 1 == 1 {
-            result[i] = true;
-        }
-    }
-    result
+    std::array::from_fn(|i| (v >> i) & 1 == 1)
 }
 
-fn coords_to_binary<const N: usize>(x: u8, y: u8) -> [bool; N] {
-    let mut result = [false; N];
-    for i in 0..N / 2 {
-        if (x >> i) & 1 == 1 {
-            result[i] = true;
-        }
-    }
-    for i in N / 2..N {
-        if (y >> i) & 1 == 1 {
-            result[i] = true;
+pub(crate) fn coords_to_binary<const N: usize>(x: u8, y: u8, board_dim: usize) -> [bool; N] {
+    assert!(
+        (x as usize) < board_dim && (y as usize) < board_dim,
+        "({x}, {y}) is off the {board_dim} x {board_dim} board"
+    );
+    std::array::from_fn(|i| {
+        if i < N / 2 {
+            (x >> i) & 1 == 1
+        } else {
+            (y >> (i - N / 2)) & 1 == 1
         }
-    }
-    result
+    })
+}
+
+pub(crate) fn u32_to_binary<const N: usize>(v: u32) -> [bool; N] {
+    assert!(N == 32 || (N < 32 && v < 1 << N));
+    std::array::from_fn(|i| (v >> i) & 1 == 1)
 }
 
-// TODO: Int to binary? 
+/// Interpret decrypted bits as an unsigned integer, least significant bit first
+pub fn binary_to_u32(bits: &[bool]) -> u32 {
+    bits.iter()
+        .take(32)
+        .enumerate()
+        .fold(0, |acc, (i, bit)| acc | ((*bit as u32) << i))
+}
 
 #[derive(Debug, Clone)]
 pub struct GameStateLocalView {
     user_id: UserId,
+    board_dim: usize,
     my_coord: (u8, u8),
     eggs_laid: Vec<Vec<bool>>,
 }
@@ -87,23 +143,38 @@ pub struct GameStateLocalView {
 
 impl GameStateLocalView {
     // x is the row index, y is the column index
-    pub fn new(x: u8, y: u8, user_id: UserId) -> Self {
+    pub fn new(x: u8, y: u8, user_id: UserId, board_dim: usize) -> Self {
         Self {
             user_id,
+            board_dim,
             my_coord: (x, y),
-            eggs_laid: vec![vec![false; BOARD_DIM]; BOARD_DIM],
+            eggs_laid: vec![vec![false; board_dim]; board_dim],
         }
     }
 
     pub fn move_player(&mut self, dir: Direction) {
+        let dim = self.board_dim;
         let (x, y) = &mut self.my_coord;
         match dir {
-            Direction::Up => *x = (*x - 1 + BOARD_DIM as u8) % BOARD_DIM as u8,
-            Direction::Down => *x = (*x + 1) % BOARD_DIM as u8,
-            Direction::Left => *y = (*y - 1 + BOARD_DIM as u8) % BOARD_DIM as u8,
-            Direction::Right => *y = (*y + 1) % BOARD_DIM as u8,
+            Direction::Up => *x = ((*x as usize + dim - 1) % dim) as u8,
+            Direction::Down => *x = ((*x as usize + 1) % dim) as u8,
+            Direction::Left => *y = ((*y as usize + dim - 1) % dim) as u8,
+            Direction::Right => *y = ((*y as usize + 1) % dim) as u8,
         }
     }
+
+    pub fn board_dim(&self) -> usize {
+        self.board_dim
+    }
+
+    pub fn coords(&self) -> (u8, u8) {
+        self.my_coord
+    }
+
+    pub fn eggs(&self) -> &[Vec<bool>] {
+        &self.eggs_laid
+    }
+
     pub fn get_egg(&mut self) -> &mut bool {
         let (x, y) = self.my_coord;
         &mut self.eggs_laid[x as usize][y as usize]
@@ -124,24 +195,22 @@ impl GameStateLocalView {
         println!("My coordinates {:?}", self.my_coord);
 
         let mut data = vec![];
-        for _ in 0..BOARD_DIM {
-            let cells = (0..BOARD_DIM).map(|_| "_".to_string()).collect_vec();
+        for _ in 0..self.board_dim {
+            let cells = (0..self.board_dim).map(|_| "_".to_string()).collect_vec();
             data.push(cells)
         }
 
         let (my_x, my_y) = self.my_coord;
         data[my_x as usize][my_y as usize] = format!("(🐓{})", self.user_id).to_string();
 
-        for x in 0..BOARD_DIM {
-            for y in 0..BOARD_DIM {
-                if self.eggs_laid[x][y] {
-                    data[x][y] = [data[x][y].to_string(), "🥚".to_string()]
-                        .join("")
-                        .to_string();
+        for (row, eggs) in data.iter_mut().zip(self.eggs_laid.iter()) {
+            for (cell, egg) in row.iter_mut().zip(eggs.iter()) {
+                if *egg {
+                    cell.push('🥚');
                 }
             }
         }
-        println!("{}", Table::from_iter(data).to_string());
+        println!("{}", Table::from_iter(data));
     }
 
     pub fn print_with_output(&self, output: &[bool]) {
@@ -150,8 +219,8 @@ impl GameStateLocalView {
         println!("My coordinates {:?}", self.my_coord);
 
         let mut data = vec![];
-        for _ in 0..BOARD_DIM {
-            let cells = (0..BOARD_DIM).map(|_| "🌫️".to_string()).collect_vec();
+        for _ in 0..self.board_dim {
+            let cells = (0..self.board_dim).map(|_| "🌫️".to_string()).collect_vec();
             data.push(cells)
         }
 
@@ -159,30 +228,35 @@ impl GameStateLocalView {
         let (x, y) = (my_x as usize, my_y as usize);
         data[x][y] = "".to_string();
 
-        for user in 0..4 {
-            if output[user] {
-                data[x][y] = [data[x][y].to_string(), format!("(🐓{})", user).to_string()].concat()
+        let players = output.len() - 1;
+        for (user, here) in output[..players].iter().enumerate() {
+            if *here {
+                data[x][y] = [data[x][y].to_string(), format!("(🐓{})", user)].concat()
             }
         }
-        if output[4] {
+        if output[players] {
             data[x][y] = [data[x][y].to_string(), "🥚".to_string()].concat()
         }
 
-        println!("{}", Table::from_iter(data).to_string());
+        println!("{}", Table::from_iter(data));
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
 pub struct GameStateEnc {
+    pub board_dim: usize,
     pub coords: Vec<Option<Word>>,
     pub eggs: Word,
+    /// The shared 32-bit accumulator updated by [`UserAction::AddInt`]
+    pub int: Word,
 }
 
 /// Encrypted input words contributed from one user
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(crate = "rocket::serde")]
 pub enum UserAction<T> {
-    InitGame { initial_eggs: T },
+    InitGame { initial_eggs: T, initial_int: T },
     SetStartingCoord { starting_coord: T },
     AddInt { user_int: T },
     MovePlayer { direction: T },
@@ -199,11 +273,11 @@ impl<T> Display for UserAction<T> {
             UserAction::InitGame { .. } => "InitGame",
             UserAction::SetStartingCoord { .. } => "SetStartingCoord",
             UserAction::AddInt { .. } => "AddInt",
-            UserAction::ViewInt { .. } => "ViewInt",
+            UserAction::ViewInt => "ViewInt",
             UserAction::MovePlayer { .. } => "MovePlayer",
-            UserAction::LayEgg { .. } => "LayEgg",
-            UserAction::PickupEgg { .. } => "PickupEgg",
-            UserAction::GetCell { .. } => "GetCell",
+            UserAction::LayEgg => "LayEgg",
+            UserAction::PickupEgg => "PickupEgg",
+            UserAction::GetCell => "GetCell",
             UserAction::Done => "Done",
         };
         write!(f, "{}", text)
@@ -213,45 +287,52 @@ impl<T> Display for UserAction<T> {
 impl UserAction<EncryptedWord> {
     pub fn init_game(ck: &ClientKey, initial_eggs: &[bool]) -> Self {
         let initial_eggs = ck.encrypt(initial_eggs);
-        Self::InitGame { initial_eggs }
+        let initial_int = ck.encrypt(u32_to_binary::<INT_BITS>(0).as_slice());
+        Self::InitGame {
+            initial_eggs,
+            initial_int,
+        }
     }
 
-    pub fn set_starting_coord(ck: &ClientKey, coords: &(u8, u8)) -> Self {
+    pub fn set_starting_coord(ck: &ClientKey, coords: &(u8, u8), board_dim: usize) -> Self {
         let (x, y) = coords;
-        let starting_coord = ck.encrypt(coords_to_binary::<16>(*x, *y).as_slice());
+        let starting_coord =
+            ck.encrypt(coords_to_binary::<COORDS_BITS>(*x, *y, board_dim).as_slice());
 
         Self::SetStartingCoord { starting_coord }
     }
 
     pub fn move_player(ck: &ClientKey, direction: Direction) -> Self {
-        let direction = u8_to_binary::<8>(direction as u8);
+        let direction = u8_to_binary::<DIRECTION_BITS>(direction as u8);
         Self::MovePlayer {
             direction: ck.encrypt(direction.as_slice()),
         }
     }
 
-    pub fn add_int(ck: &ClientKey, user_int: u8) -> Self {
-        // TODO: are ints u8? write u8_to_binary fxn for ints   
-        let user_int = u8_to_binary::<8>(user_int as u8);
+    pub fn add_int(ck: &ClientKey, user_int: u32) -> Self {
+        let user_int = u32_to_binary::<INT_BITS>(user_int);
         Self::AddInt {
             user_int: ck.encrypt(user_int.as_slice()),
         }
     }
- 
+
     pub fn unpack(&self, user_id: UserId) -> UserAction<Word> {
-        set_parameter_set(PARAMETER);
         match &self {
-            UserAction::InitGame { initial_eggs } => UserAction::InitGame {
-                initial_eggs: unpack_word(initial_eggs, user_id),
+            UserAction::InitGame {
+                initial_eggs,
+                initial_int,
+            } => UserAction::InitGame {
+                initial_eggs: initial_eggs.unpack(user_id),
+                initial_int: initial_int.unpack(user_id),
             },
             UserAction::SetStartingCoord { starting_coord } => UserAction::SetStartingCoord {
-                starting_coord: unpack_word(starting_coord, user_id),
+                starting_coord: starting_coord.unpack(user_id),
             },
             UserAction::MovePlayer { direction } => UserAction::MovePlayer {
-                direction: unpack_word(direction, user_id),
+                direction: direction.unpack(user_id),
             },
             UserAction::AddInt { user_int } => UserAction::AddInt {
-                user_int: unpack_word(user_int, user_id),
+                user_int: user_int.unpack(user_id),
             },
             UserAction::ViewInt => UserAction::ViewInt,
             UserAction::LayEgg => UserAction::LayEgg,
@@ -262,36 +343,140 @@ impl UserAction<EncryptedWord> {
     }
 }
 
-fn unpack_word(word: &EncryptedWord, user_id: UserId) -> Word {
-    word.unseed::<Vec<Vec<u64>>>()
-        .key_switch(user_id)
-        .extract_all()
+impl UserAction<Word> {
+    /// Circuits given a word of the wrong width panic in the FHE run, so turn it away on
+    /// submission. The eggs depend on the board, so their count is checked with it
+    pub(crate) fn check_widths(&self) -> Result<(), Error> {
+        let (word, bits, expect) = match self {
+            UserAction::InitGame { initial_int, .. } => ("initial_int", initial_int, INT_BITS),
+            UserAction::SetStartingCoord { starting_coord } => {
+                ("starting_coord", starting_coord, COORDS_BITS)
+            }
+            UserAction::AddInt { user_int } => ("user_int", user_int, INT_BITS),
+            UserAction::MovePlayer { direction } => ("direction", direction, DIRECTION_BITS),
+            _ => return Ok(()),
+        };
+        if bits.len() != expect {
+            return Err(Error::WrongWordWidth {
+                word: word.to_string(),
+                expect,
+                got: bits.len(),
+            });
+        }
+        Ok(())
+    }
+}
+
+/// A word of the output, which only `recipient` is meant to decrypt
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NamedOutput {
+    pub name: String,
+    pub recipient: UserId,
+    word: Word,
+}
+
+impl NamedOutput {
+    pub(crate) fn new(name: String, recipient: UserId, word: Word) -> Self {
+        Self {
+            name,
+            recipient,
+            word,
+        }
+    }
 }
 
+/// The words to decrypt after a run, each named and addressed to a player.
+/// Decrypting a word takes a share from every player. Recipients keep the share of their own
+/// words to themselves, so only they can decrypt them
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CircuitOutput {
-    cell: Word,
+    outputs: Vec<NamedOutput>,
 }
 
 impl CircuitOutput {
-    pub(crate) fn new(cell: Word) -> Self {
-        Self { cell }
+    pub(crate) fn new(outputs: Vec<NamedOutput>) -> Self {
+        Self { outputs }
+    }
+
+    /// Name of the output with the cell of `user_id`: who stands on it, then whether it has an egg
+    pub fn cell_name(user_id: UserId) -> String {
+        format!("cell_{user_id}")
+    }
+
+    /// Name of the output with the shared integer, for `user_id` who asked to view it
+    pub fn int_name(user_id: UserId) -> String {
+        format!("int_{user_id}")
+    }
+
+    pub fn outputs(&self) -> impl Iterator<Item = &NamedOutput> {
+        self.outputs.iter()
+    }
+
+    pub fn get(&self, name: &str) -> Option<&NamedOutput> {
+        self.outputs.iter().find(|output| output.name == name)
+    }
+
+    /// The outputs `user_id` gets to decrypt
+    pub fn received_by(&self, user_id: UserId) -> impl Iterator<Item = &NamedOutput> {
+        self.outputs
+            .iter()
+            .filter(move |output| output.recipient == user_id)
+    }
+
+    /// Whether the share of `user_id` for the output `name` may be published: the output
+    /// exists, and `user_id` isn't its recipient
+    pub(crate) fn check_shareable(&self, name: &str, user_id: UserId) -> Result<(), Error> {
+        let output = self.get(name).ok_or_else(|| Error::OutputNotFound {
+            name: name.to_string(),
+        })?;
+        if output.recipient == user_id {
+            return Err(Error::OwnDecryptionShare {
+                user_id,
+                name: name.to_string(),
+            });
+        }
+        Ok(())
     }
 
-    pub fn gen_decryption_share(&self, ck: &ClientKey) -> DecryptionShare {
+    /// My share of the output `name`
+    pub fn gen_decryption_share(&self, ck: &ClientKey, name: &str) -> Option<DecryptionShare> {
         let dec_share = self
-            .cell
+            .get(name)?
+            .word
             .iter()
             .map(|out_bit| ck.gen_decryption_share(out_bit))
             .collect_vec();
-        dec_share
+        Some(dec_share)
+    }
+
+    /// My shares of the outputs addressed to the others, to submit
+    pub fn gen_decryption_shares(
+        &self,
+        ck: &ClientKey,
+        my_id: UserId,
+    ) -> Vec<(String, DecryptionShare)> {
+        self.outputs
+            .iter()
+            .filter(|output| output.recipient != my_id)
+            .map(|output| {
+                let share = self.gen_decryption_share(ck, &output.name).expect("exists");
+                (output.name.clone(), share)
+            })
+            .collect_vec()
     }
 
-    pub fn decrypt(&self, ck: &ClientKey, dss: &[DecryptionShare]) -> Vec<bool> {
+    /// Decrypt the output `name` with the shares of every player, in user ID order
+    pub fn decrypt(
+        &self,
+        ck: &ClientKey,
+        name: &str,
+        dss: &[DecryptionShare],
+    ) -> Option<Vec<bool>> {
         // A DecryptionShare is user i's contribution to word j.
         // To decrypt word j at bit position k. We need to extract the position k of user i's share.
         let decrypted_bits = self
-            .cell
+            .get(name)?
+            .word
             .iter()
             .enumerate()
             .map(|(bit_k, fhe_bit)| {
@@ -299,7 +484,7 @@ impl CircuitOutput {
                 ck.aggregate_decryption_shares(fhe_bit, &shares_for_bit_k)
             })
             .collect_vec();
-        decrypted_bits
+        Some(decrypted_bits)
     }
 }
 
@@ -320,6 +505,54 @@ pub(crate) enum Error {
     GameNotInitedYet,
     #[error("Cells not found")]
     CellNotFound,
+    #[error("Need at least {min} players to start, got {got}")]
+    NotEnoughPlayers { min: usize, got: usize },
+    #[error("Expect {expect} eggs for the board but got {got}")]
+    WrongBoardSize { expect: usize, got: usize },
+    #[error("Server runs the {expect} backend but got {got}")]
+    WrongBackend { expect: Backend, got: Backend },
+    #[error("Game #{game_id} not found")]
+    GameNotFound { game_id: GameId },
+    #[error("Invalid game config: {0}")]
+    InvalidGameConfig(String),
+    #[error("phantom-zone holds one server key per process, and another game took it")]
+    ServerKeyTaken,
+    #[error("phantom-zone holds one server key per process, for game #{game_id}. Another PhantomZone game needs the seed of that game, and its key cached in the data directory")]
+    PhantomZoneTaken { game_id: GameId },
+    #[error("Send the registration token as `Authorization: Bearer <token>`")]
+    MissingToken,
+    #[error("The token doesn't belong to user #{user_id}")]
+    Unauthorized { user_id: UserId },
+    #[error("User #{user_id} must share every output addressed to the other players")]
+    MissingDecryptionShares { user_id: UserId },
+    #[error("Output {name} not found")]
+    OutputNotFound { name: String },
+    #[error("Only user #{user_id} may decrypt {name}, so their share stays with them")]
+    OwnDecryptionShare { user_id: UserId, name: String },
+    #[error("User #{user_id} already acted this round")]
+    AlreadyActed { user_id: UserId },
+    #[error("Timed out waiting for {state} in round {round}")]
+    WaitTimedOut { state: ServerState, round: usize },
+    #[error("User #{user_id} has no upload in progress")]
+    UploadNotStarted { user_id: UserId },
+    #[error("Expect the chunk at offset {expect} but got {got}")]
+    WrongUploadOffset { expect: u64, got: u64 },
+    #[error("Invalid upload: {0}")]
+    InvalidUpload(String),
+    #[error("Server key shares may take at most {max} bytes")]
+    SksTooLarge { max: u64 },
+    #[error("Deriving the server key failed: {reason}")]
+    ServerKeyFailed { reason: String },
+    #[error("Saving failed: {0}")]
+    SaveFailed(String),
+    #[error("The FHE run failed, act again: {reason}")]
+    FheRunFailed { reason: String },
+    #[error("Expect {expect} bits for {word} but got {got}")]
+    WrongWordWidth {
+        word: String,
+        expect: usize,
+        got: usize,
+    },
 }
 
 #[derive(Responder)]
@@ -328,25 +561,65 @@ pub(crate) enum ErrorResponse {
     ServerError(String),
     #[response(status = 404, content_type = "json")]
     NotFoundError(String),
+    #[response(status = 401, content_type = "json")]
+    Unauthorized(String),
+    #[response(status = 408, content_type = "json")]
+    Timeout(String),
+    #[response(status = 400, content_type = "json")]
+    BadRequest(String),
+    #[response(status = 413, content_type = "json")]
+    PayloadTooLarge(String),
+    #[response(status = 409, content_type = "json")]
+    Conflict(String),
 }
 
 impl From<Error> for ErrorResponse {
     fn from(error: Error) -> Self {
         match error {
+            Error::ServerKeyFailed { .. } | Error::SaveFailed(_) => {
+                ErrorResponse::ServerError(error.to_string())
+            }
+            // The request came at the wrong time for the game
             Error::WrongServerState { .. }
-            | Error::CipherNotFound { .. }
             | Error::GameNotInitedYet
-            | Error::CellNotFound => ErrorResponse::ServerError(error.to_string()),
-            Error::DecryptionShareNotFound { .. }
+            | Error::NotEnoughPlayers { .. }
+            | Error::AlreadyActed { .. }
+            | Error::WrongUploadOffset { .. }
+            | Error::FheRunFailed { .. }
+            | Error::PhantomZoneTaken { .. }
+            | Error::ServerKeyTaken => ErrorResponse::Conflict(error.to_string()),
+            Error::WrongBoardSize { .. }
+            | Error::WrongBackend { .. }
+            | Error::InvalidGameConfig(_)
+            | Error::MissingDecryptionShares { .. }
+            | Error::OwnDecryptionShare { .. }
+            | Error::InvalidUpload(_)
+            | Error::WrongWordWidth { .. } => ErrorResponse::BadRequest(error.to_string()),
+            Error::CipherNotFound { .. }
+            | Error::CellNotFound
+            | Error::DecryptionShareNotFound { .. }
+            | Error::UploadNotStarted { .. }
+            | Error::GameNotFound { .. }
+            | Error::OutputNotFound { .. }
             | Error::UnregisteredUser { .. }
             | Error::OutputNotReady => ErrorResponse::NotFoundError(error.to_string()),
+            Error::MissingToken | Error::Unauthorized { .. } => {
+                ErrorResponse::Unauthorized(error.to_string())
+            }
+            Error::WaitTimedOut { .. } => ErrorResponse::Timeout(error.to_string()),
+            Error::SksTooLarge { .. } => ErrorResponse::PayloadTooLarge(error.to_string()),
         }
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+/// The states come in the order a game goes through them. Each round goes from
+/// `ReadyForActions` to `CompletedFhe`
+#[derive(
+    Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, FromFormField,
+)]
 pub enum ServerState {
     /// Users are allowed to join the computation
+    #[default]
     ReadyForJoining,
     /// We can now accept server key shares
     ReadyForServerKeyShares,
@@ -383,49 +656,259 @@ impl Display for ServerState {
 
 pub(crate) type MutexServerStorage = Arc<Mutex<ServerStorage>>;
 
-#[derive(Debug)]
+/// The steps of an FHE run, counting the gates evaluated as the run goes through them in order
+#[derive(Debug, Clone)]
+pub(crate) struct FheProgress(Arc<std::sync::Mutex<Vec<StepProgress>>>);
+
+impl FheProgress {
+    pub(crate) fn new(steps: Vec<StepProgress>) -> Self {
+        Self(Arc::new(std::sync::Mutex::new(steps)))
+    }
+
+    pub(crate) fn steps(&self) -> Vec<StepProgress> {
+        self.0.lock().unwrap().clone()
+    }
+
+    /// Count `gates` more evaluated in the first step not done yet
+    fn add(&self, gates: usize) {
+        let mut steps = self.0.lock().unwrap();
+        if let Some(step) = steps.iter_mut().find(|step| !step.is_done()) {
+            step.gates_done = step.gates_total.min(step.gates_done + gates);
+        }
+    }
+
+    /// Run `f`, counting the gates it evaluates on this thread
+    pub(crate) fn scope<R>(&self, f: impl FnOnce() -> R) -> R {
+        let progress = self.clone();
+        report_gates(Arc::new(move |gates| progress.add(gates)), f)
+    }
+}
+
+/// The games hosted by the server. A game ID is its index
+#[derive(Debug, Default)]
+pub(crate) struct Games {
+    /// Saved games that failed to load are left empty, so the others keep their IDs
+    games: Mutex<Vec<Option<MutexServerStorage>>>,
+    /// Where games are saved, if the server keeps them across restarts
+    storage: Option<Arc<Storage>>,
+    /// The first PhantomZone game and its seed. phantom-zone holds one server key per process,
+    /// so later PhantomZone games must be able to reuse the key of that game
+    phantom_zone_game: OnceLock<(GameId, Seed)>,
+}
+
+impl Games {
+    /// Carry on with the games loaded from `storage`
+    pub(crate) fn restore(storage: Arc<Storage>, games: Vec<Option<ServerStorage>>) -> Self {
+        let phantom_zone_game = OnceLock::new();
+        let games = games
+            .into_iter()
+            .map(|ss| {
+                ss.map(|mut ss| {
+                    ss.storage = Some(storage.clone());
+                    if ss.param.backend == Backend::PhantomZone {
+                        phantom_zone_game.get_or_init(|| (ss.id, ss.param.seed));
+                    }
+                    Arc::new(Mutex::new(ss))
+                })
+            })
+            .collect_vec();
+        Self {
+            games: Mutex::new(games),
+            storage: Some(storage),
+            phantom_zone_game,
+        }
+    }
+
+    /// Add a game. A PhantomZone game is turned away unless it is the first, or can load the
+    /// key of the first from the key cache: it needs the same seed, and the key cached already.
+    /// Otherwise its players would upload their shares only for the key to be taken
+    pub(crate) async fn create(&self, mut ss: ServerStorage) -> Result<GameId, Error> {
+        let mut games = self.games.lock().await;
+        let game_id = games.len();
+        if ss.param.backend == Backend::PhantomZone {
+            let (holder, seed) = self
+                .phantom_zone_game
+                .get_or_init(|| (game_id, ss.param.seed));
+            let first = *holder == game_id;
+            let reuses_key = self.storage.is_some()
+                && *seed == ss.param.seed
+                && ServerKey::is_cached_pz_key_held();
+            if !first && !reuses_key {
+                return Err(Error::PhantomZoneTaken { game_id: *holder });
+            }
+        }
+        ss.id = game_id;
+        ss.storage = self.storage.clone();
+        ss.save();
+        games.push(Some(Arc::new(Mutex::new(ss))));
+        Ok(game_id)
+    }
+
+    /// Each game has its own lock, so games don't wait on each other
+    pub(crate) async fn get(&self, game_id: GameId) -> Result<MutexServerStorage, Error> {
+        let games = self.games.lock().await;
+        games
+            .get(game_id)
+            .cloned()
+            .flatten()
+            .ok_or(Error::GameNotFound { game_id })
+    }
+
+    /// Wait until the changes to the games so far are on disk
+    pub(crate) async fn flush(&self) {
+        let Some(storage) = self.storage.clone() else {
+            return;
+        };
+        match rocket::tokio::task::spawn_blocking(move || storage.flush()).await {
+            Ok(Ok(())) => {}
+            Ok(Err(err)) => println!("Failed to save the games: {err}"),
+            Err(err) => println!("Failed to save the games: {err}"),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
 pub(crate) struct ServerStorage {
-    pub(crate) seed: Seed,
+    /// Set by [`Games::create`]
+    pub(crate) id: GameId,
+    #[serde(skip)]
+    pub(crate) storage: Option<Arc<Storage>>,
+    pub(crate) param: Param,
+    /// Submitted until the server key is derived. Saved in their own files
+    #[serde(skip)]
+    pub(crate) server_key_shares: HashMap<UserId, ServerKeyShare>,
+    /// Server key shares still uploading. They start over if the server restarts
+    #[serde(skip)]
+    pub(crate) sks_uploads: HashMap<UserId, SksUpload>,
+    /// Derived once every user submitted a server key share. Saved in the key cache
+    #[serde(skip)]
+    pub(crate) server_key: Option<ServerKey>,
+    /// Names the server key in the key cache, see [`server_key_hash`]
+    pub(crate) server_key_hash: Option<String>,
+    pub(crate) min_players: usize,
+    pub(crate) max_players: usize,
     pub(crate) state: ServerState,
     pub(crate) users: Vec<UserRecord>,
-
-    // TODO: state_int
-    pub(crate) state_int: Option<EncryptedWord>,
-
     pub(crate) game_state: Option<GameStateEnc>,
     pub(crate) action_queue: Vec<(UserId, UserAction<Word>)>,
-    // in this case it is the user's cell
+    // the user's cell, or the shared integer for a ViewInt
     pub(crate) circuit_output: Option<CircuitOutput>,
     pub(crate) round: usize,
+    /// When the actions of a simultaneous round close at the latest
+    pub(crate) actions_deadline: Option<SystemTime>,
+    /// (output name, user_id) -> the share of `user_id` for the output this round
     pub(crate) decryption_shares: DecryptionSharesMap,
+    /// Why the last FHE run failed, until the next action
+    pub(crate) fhe_failure: Option<String>,
+    /// Filled in by the FHE run in progress
+    #[serde(skip)]
+    pub(crate) fhe_progress: Option<FheProgress>,
+    /// Set while the shares are out for [`KeyDerivation::run`]
+    #[serde(skip)]
+    pub(crate) deriving_server_key: bool,
+    /// The latest dashboard, watched by the event streams
+    #[serde(skip, default = "dashboard_updates")]
+    pub(crate) updates: watch::Sender<Dashboard>,
+}
+
+fn dashboard_updates() -> watch::Sender<Dashboard> {
+    watch::channel(Dashboard::default()).0
 }
 
 impl ServerStorage {
-    pub(crate) fn new(seed: Seed) -> Self {
+    pub(crate) fn new(param: Param, min_players: usize, max_players: usize) -> Self {
         Self {
-            seed,
+            id: 0,
+            storage: None,
+            param,
+            server_key_shares: HashMap::new(),
+            sks_uploads: HashMap::new(),
+            server_key: None,
+            server_key_hash: None,
+            min_players,
+            max_players,
             state: ServerState::ReadyForJoining,
             users: vec![],
-
-            state_int: None, // Should this be encrypted? -- Option
-
             game_state: None,
             action_queue: vec![],
             circuit_output: None,
             round: 0,
+            actions_deadline: None,
             decryption_shares: HashMap::new(),
+            fhe_failure: None,
+            fhe_progress: None,
+            deriving_server_key: false,
+            updates: dashboard_updates(),
+        }
+    }
+
+    /// Let the event streams and the storage know the game changed
+    pub(crate) fn changed(&self) {
+        self.publish();
+        self.save();
+    }
+
+    pub(crate) fn publish(&self) {
+        let dashboard = self.get_dashboard();
+        self.updates.send_if_modified(|current| {
+            let modified = *current != dashboard;
+            *current = dashboard;
+            modified
+        });
+    }
+
+    /// Watch the dashboard, starting from the current one
+    pub(crate) fn subscribe(&self) -> watch::Receiver<Dashboard> {
+        self.publish();
+        self.updates.subscribe()
+    }
+
+    /// Snapshot the game if the server keeps games across restarts.
+    /// A failed snapshot doesn't fail the request, the game carries on in memory
+    pub(crate) fn save(&self) {
+        if let Some(storage) = &self.storage {
+            if let Err(err) = storage.save(self) {
+                println!("Failed to save game #{}: {err}", self.id);
+            }
+        }
+    }
+
+    /// Set up the current thread for this game: its parameters, and its server key once derived
+    pub(crate) fn init_thread(&self) {
+        self.param.init_thread();
+        if let Some(server_key) = &self.server_key {
+            server_key.install();
         }
     }
 
-    pub(crate) fn add_user(&mut self, name: &str) -> RegisteredUser {
+    pub(crate) fn add_user(&mut self, name: &str) -> Registration {
         let user_id: usize = self.users.len();
+        let mut token = [0u8; 32];
+        thread_rng().fill_bytes(&mut token);
+        let token = hex::encode(token);
         self.users.push(UserRecord {
             id: user_id,
             name: name.to_string(),
+            token_sha256: sha256_hex(token.as_bytes()),
             storage: UserStorage::Empty,
             ready_for_new_round: false,
         });
-        RegisteredUser::new(user_id, name)
+        Registration {
+            user: RegisteredUser::new(user_id, name),
+            token,
+        }
+    }
+
+    /// Check `token` is the one `user_id` got when registering
+    pub(crate) fn authenticate(&self, user_id: UserId, token: &str) -> Result<(), Error> {
+        let user = self
+            .users
+            .get(user_id)
+            .ok_or(Error::UnregisteredUser { user_id })?;
+        let token_sha256 = sha256_hex(token.as_bytes());
+        verify_slices_are_equal(user.token_sha256.as_bytes(), token_sha256.as_bytes())
+            .map_err(|_| Error::Unauthorized { user_id })
     }
 
     pub(crate) fn ensure(&self, state: ServerState) -> Result<(), Error> {
@@ -447,7 +930,7 @@ impl ServerStorage {
     pub(crate) fn check_cipher_submission(&self) -> bool {
         self.users
             .iter()
-            .all(|user| matches!(user.storage, UserStorage::Sks(..)))
+            .all(|user| matches!(user.storage, UserStorage::Sks))
     }
 
     pub(crate) fn check_setup_game_complete(&self) -> bool {
@@ -460,16 +943,85 @@ impl ServerStorage {
         self.users.iter().all(|user| user.ready_for_new_round)
     }
 
-    pub(crate) fn get_sks(&mut self) -> Result<Vec<ServerKeyShare>, Error> {
-        let mut server_key_shares = vec![];
-        for (user_id, user) in self.users.iter_mut().enumerate() {
-            if let Some(sks) = user.storage.get_cipher_sks() {
-                server_key_shares.push(sks.clone());
-            } else {
-                return Err(Error::CipherNotFound { user_id });
+    /// Open the current round for actions. Returns how long until it closes, if it has a deadline
+    pub(crate) fn open_round(&mut self) -> Option<Duration> {
+        self.transit(ServerState::ReadyForActions);
+        let deadline = match self.param.round_mode {
+            RoundMode::Simultaneous {
+                deadline: Some(deadline),
+            } => Some(Duration::from_secs(deadline)),
+            _ => None,
+        };
+        self.actions_deadline = deadline.map(|deadline| SystemTime::now() + deadline);
+        deadline
+    }
+
+    /// Whether the round got the actions it waits for. A turn round waits for the first one.
+    /// A simultaneous round waits for one per player, or any once its deadline passed
+    pub(crate) fn check_actions_complete(&self) -> bool {
+        match self.param.round_mode {
+            RoundMode::Turns => !self.action_queue.is_empty(),
+            RoundMode::Simultaneous { .. } => {
+                let overdue = self
+                    .actions_deadline
+                    .is_some_and(|deadline| deadline <= SystemTime::now());
+                self.action_queue.len() == self.users.len()
+                    || (overdue && !self.action_queue.is_empty())
+            }
+        }
+    }
+
+    /// Take the shares out to derive the server key off the game lock, see
+    /// [`KeyDerivation::run`]. `None` while another derivation is under way
+    pub(crate) fn start_key_derivation(&mut self) -> Result<Option<KeyDerivation>, Error> {
+        if self.deriving_server_key {
+            return Ok(None);
+        }
+        let server_key_shares = self.take_sks()?;
+        self.deriving_server_key = true;
+        Ok(Some(KeyDerivation {
+            param: self.param.clone(),
+            storage: self.storage.clone(),
+            server_key_shares,
+        }))
+    }
+
+    /// Keep the key derived. If the derivation failed, the shares it handed back are kept to try
+    /// again, and the players whose shares were lost upload theirs again
+    pub(crate) fn end_key_derivation(&mut self, derived: DerivedKey) -> Result<(), Error> {
+        self.deriving_server_key = false;
+        match derived {
+            Ok((server_key, hash)) => {
+                self.server_key = Some(server_key);
+                self.server_key_hash = hash;
+                Ok(())
+            }
+            Err((err, server_key_shares)) => {
+                for (user_id, sks) in server_key_shares.into_iter().enumerate() {
+                    // A share submitted again meanwhile is newer
+                    self.server_key_shares.entry(user_id).or_insert(sks);
+                }
+                for user in self.users.iter_mut() {
+                    if !self.server_key_shares.contains_key(&user.id) {
+                        user.storage = UserStorage::Empty;
+                    }
+                }
+                Err(err)
             }
         }
-        Ok(server_key_shares)
+    }
+
+    fn take_sks(&mut self) -> Result<Vec<ServerKeyShare>, Error> {
+        if let Some(user_id) =
+            (0..self.users.len()).find(|user_id| !self.server_key_shares.contains_key(user_id))
+        {
+            return Err(Error::CipherNotFound { user_id });
+        }
+        let mut server_key_shares = std::mem::take(&mut self.server_key_shares)
+            .into_iter()
+            .collect_vec();
+        server_key_shares.sort_by_key(|(user_id, _)| *user_id);
+        Ok(server_key_shares.into_iter().map(|(_, sks)| sks).collect())
     }
 
     pub(crate) fn get_dashboard(&self) -> Dashboard {
@@ -477,55 +1029,213 @@ impl ServerStorage {
             &self.state,
             &self.users.iter().map_into().collect_vec(),
             self.round,
+            self.min_players,
+            self.max_players,
+            self.fhe_failure.clone(),
+            self.fhe_progress
+                .as_ref()
+                .map(FheProgress::steps)
+                .unwrap_or_default(),
         )
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
 pub(crate) struct UserRecord {
     pub(crate) id: UserId,
     pub(crate) name: String,
+    /// Of the token handed out by the registration, which proves later requests come from
+    /// this user. Only the hash is kept, so the saved games hold no usable token
+    pub(crate) token_sha256: String,
     pub(crate) storage: UserStorage,
     pub(crate) ready_for_new_round: bool,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
 pub(crate) enum UserStorage {
     Empty,
-    Sks(Box<ServerKeyShare>),
+    /// The share is kept in [`ServerStorage::server_key_shares`]
+    Sks,
     StartingCoords,
-    DecryptionShare(Option<DecryptionShare>),
+    /// The shares are kept in [`ServerStorage::decryption_shares`]
+    DecryptionShares {
+        submitted: bool,
+    },
 }
 
-impl UserStorage {
-    pub(crate) fn get_cipher_sks(&self) -> Option<&ServerKeyShare> {
-        match self {
-            Self::Sks(sks) => Some(sks),
-            _ => None,
-        }
+/// (output name, user_id) -> the share of `user_id` for the output, see [`CircuitOutput`]
+pub type DecryptionSharesMap = HashMap<(String, UserId), DecryptionShare>;
+
+/// How the bytes of an upload are compressed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub enum Compression {
+    None,
+    #[default]
+    Zstd,
+}
+
+/// Opens a chunked upload of a server key share: its msgpack, compressed with `compression`,
+/// takes `size` bytes and hashes to `sha256`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub(crate) struct SksUploadStart {
+    pub(crate) size: u64,
+    pub(crate) sha256: String,
+    pub(crate) compression: Compression,
+}
+
+impl SksUploadStart {
+    /// The bytes to upload for `sks`, and how to announce them
+    pub(crate) fn encode(
+        sks: &ServerKeyShare,
+        compression: Compression,
+    ) -> Result<(Self, Vec<u8>), anyhow::Error> {
+        let body = msgpack::to_compact_vec(sks)?;
+        let body = match compression {
+            Compression::None => body,
+            Compression::Zstd => zstd::encode_all(&body[..], 0)?,
+        };
+        let start = Self {
+            size: body.len() as u64,
+            sha256: sha256_hex(&body),
+            compression,
+        };
+        Ok((start, body))
     }
+}
+
+/// The key of a game, or why it couldn't be derived along with the shares still there
+pub(crate) type DerivedKey = Result<(ServerKey, Option<String>), (Error, Vec<ServerKeyShare>)>;
 
-    pub(crate) fn get_mut_decryption_share(&mut self) -> Option<&mut Option<DecryptionShare>> {
-        match self {
-            Self::DecryptionShare(ds) => Some(ds),
+/// The shares of a game, taken out by [`ServerStorage::start_key_derivation`]
+pub(crate) struct KeyDerivation {
+    param: Param,
+    storage: Option<Arc<Storage>>,
+    server_key_shares: Vec<ServerKeyShare>,
+}
+
+impl KeyDerivation {
+    /// Aggregate the shares into the server key of the game, or load the key aggregated before
+    /// from the same seed and shares, named by the returned hash. Long running, so it runs on
+    /// a blocking thread
+    pub(crate) fn run(self) -> DerivedKey {
+        self.param.init_thread();
+        // Only worth hashing when there is a cache to look into
+        let hash = self
+            .storage
+            .as_ref()
+            .map(|_| server_key_hash(&self.param.seed, &self.server_key_shares));
+        let cached = match (&self.storage, &hash) {
+            (Some(storage), Some(hash)) => storage.load_server_key(hash).unwrap_or_else(|err| {
+                println!("Failed to load the cached server key: {err}");
+                None
+            }),
             _ => None,
+        };
+
+        let server_key = match cached {
+            Some(server_key) => {
+                println!("Loaded the cached server key");
+                Ok(server_key)
+            }
+            None => derive_server_key(self.param.backend, &self.server_key_shares).inspect(
+                |server_key| {
+                    if let (Some(storage), Some(hash)) = (&self.storage, &hash) {
+                        if let Err(err) = storage.save_server_key(hash, server_key) {
+                            println!("Failed to cache the server key: {err}");
+                        }
+                    }
+                },
+            ),
+        }
+        .and_then(|server_key| server_key.claim(hash.as_deref()));
+        match server_key {
+            Ok(server_key) => Ok((server_key, hash)),
+            Err(err) => Err((err, self.server_key_shares)),
         }
     }
 }
 
-/// ([`Word`] index, user_id) -> decryption share
-pub type DecryptionSharesMap = HashMap<(usize, UserId), DecryptionShare>;
+/// The bytes of a server key share received so far
+#[derive(Debug)]
+pub(crate) struct SksUpload {
+    pub(crate) start: SksUploadStart,
+    bytes: Vec<u8>,
+    /// Bytes the share may take, both as uploaded and decompressed
+    max_size: u64,
+}
 
-#[derive(Serialize, Deserialize)]
-#[serde(crate = "rocket::serde")]
-pub(crate) struct SksSubmission {
-    pub(crate) user_id: UserId,
-    pub(crate) sks: ServerKeyShare,
+impl SksUpload {
+    /// Turned away if the share announced takes more than `max_size` bytes
+    pub(crate) fn new(start: SksUploadStart, max_size: u64) -> Result<Self, Error> {
+        if start.size > max_size {
+            return Err(Error::SksTooLarge { max: max_size });
+        }
+        Ok(Self {
+            start,
+            bytes: vec![],
+            max_size,
+        })
+    }
+
+    pub(crate) fn received(&self) -> u64 {
+        self.bytes.len() as u64
+    }
+
+    /// Append `chunk`, which must start where the bytes received so far end
+    pub(crate) fn append(&mut self, offset: u64, chunk: &[u8]) -> Result<(), Error> {
+        if offset != self.received() {
+            return Err(Error::WrongUploadOffset {
+                expect: self.received(),
+                got: offset,
+            });
+        }
+        if self.received() + chunk.len() as u64 > self.start.size {
+            return Err(Error::InvalidUpload(format!(
+                "More than the {} bytes announced",
+                self.start.size
+            )));
+        }
+        self.bytes.extend_from_slice(chunk);
+        Ok(())
+    }
+
+    /// The share, once every byte arrived and they hash as announced
+    pub(crate) fn finish(&self) -> Result<ServerKeyShare, Error> {
+        if self.received() != self.start.size {
+            return Err(Error::InvalidUpload(format!(
+                "Received {} of the {} bytes announced",
+                self.received(),
+                self.start.size
+            )));
+        }
+        if sha256_hex(&self.bytes) != self.start.sha256 {
+            return Err(Error::InvalidUpload("SHA-256 mismatch".to_string()));
+        }
+        let mut decoded = vec![];
+        let bytes = match self.start.compression {
+            Compression::None => &self.bytes,
+            Compression::Zstd => {
+                // A few bytes can decompress to any size, so stop past the limit
+                zstd::stream::Decoder::with_buffer(&self.bytes[..])
+                    .and_then(|decoder| decoder.take(self.max_size + 1).read_to_end(&mut decoded))
+                    .map_err(|err| Error::InvalidUpload(err.to_string()))?;
+                if decoded.len() as u64 > self.max_size {
+                    return Err(Error::SksTooLarge { max: self.max_size });
+                }
+                &decoded
+            }
+        };
+        msgpack::from_slice(bytes).map_err(|err| Error::InvalidUpload(err.to_string()))
+    }
 }
 
 #[derive(Serialize, Deserialize)]
 #[serde(crate = "rocket::serde")]
 pub(crate) struct DecryptionShareSubmission {
     pub(crate) user_id: UserId,
-    pub(crate) decryption_share: AnnotatedDecryptionShare,
+    pub(crate) decryption_shares: Vec<AnnotatedDecryptionShare>,
 }